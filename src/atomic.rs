@@ -0,0 +1,105 @@
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::format_err;
+use anyhow::Result;
+
+/// Writes `contents` into `path` atomically. The data is first written to a
+/// temporary file next to `path`, which is then renamed over `path`, so
+/// readers either see the previous contents or the new ones, never a
+/// truncated file.
+pub fn write<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, contents: C) -> Result<()> {
+    let path = path.as_ref();
+    let tmp = sibling(path, "tmp");
+    let result = fs::write(&tmp, contents).and_then(|()| fs::rename(&tmp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result.map_err(Into::into)
+}
+
+/// Moves the directory `src` into `dst`, replacing `dst` if it already
+/// exists. The previous `dst` is renamed aside and `src` renamed into its
+/// place, each step is atomic but `dst` is briefly missing between both.
+/// When the second rename fails, the previous contents are restored.
+pub fn replace_dir<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dst: Q) -> Result<()> {
+    let (src, dst) = (src.as_ref(), dst.as_ref());
+    if !dst.exists() {
+        fs::rename(src, dst)?;
+        return Ok(());
+    }
+
+    let old = sibling(dst, "old");
+    if old.exists() {
+        fs::remove_dir_all(&old)?;
+    }
+    fs::rename(dst, &old)?;
+    if let Err(err) = fs::rename(src, dst) {
+        fs::rename(&old, dst)?;
+        return Err(format_err!(
+            "cannot replace {path}: {err}",
+            path = dst.display()
+        ));
+    }
+    fs::remove_dir_all(&old)?;
+    Ok(())
+}
+
+/// Returns a path next to `path`, with the extension `ext` appended to its
+/// file name, eg. `vendor` becomes `vendor.tmp`.
+pub fn sibling<P: AsRef<Path>>(path: P, ext: &str) -> PathBuf {
+    let path = path.as_ref();
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(".");
+    name.push(ext);
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_utils::read_to_string;
+    use crate::test_utils::tempdir;
+    use crate::test_utils::write_to;
+
+    #[test]
+    fn test_sibling() {
+        assert_eq!(PathBuf::from("a/vendor.tmp"), sibling("a/vendor", "tmp"));
+        assert_eq!(PathBuf::from("spec.yml.old"), sibling("spec.yml", "old"));
+    }
+
+    #[test]
+    fn test_write_replaces_contents() -> Result<()> {
+        let root = tempdir();
+        let path = root.path().join("file.yml");
+        write_to(&path, "previous contents");
+
+        write(&path, "new")?;
+
+        assert_eq!("new", read_to_string(&path));
+        assert!(!sibling(&path, "tmp").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_replace_dir() -> Result<()> {
+        let root = tempdir();
+        let src = root.path().join("src");
+        let dst = root.path().join("dst");
+        fs::create_dir_all(&src)?;
+        fs::create_dir_all(&dst)?;
+        write_to(src.join("new"), "");
+        write_to(dst.join("old"), "");
+
+        replace_dir(&src, &dst)?;
+
+        assert!(!src.exists());
+        assert!(dst.join("new").exists());
+        assert!(!dst.join("old").exists());
+        assert!(!sibling(&dst, "old").exists());
+        Ok(())
+    }
+}
@@ -1,3 +1,4 @@
+use std::fs;
 use std::fs::create_dir_all;
 use std::fs::remove_dir_all;
 use std::path::PathBuf;
@@ -7,16 +8,26 @@ use anyhow::format_err;
 use anyhow::Result;
 use sha2::Digest;
 
+pub use self::entry::human_age;
+pub use self::entry::human_size;
+pub use self::entry::CacheEntry;
+use self::entry::Metadata;
+use crate::config::Config;
 use crate::deps::Dependency;
 use crate::lock::Lock;
 use crate::preset::Preset;
 use crate::repository::Repository;
+use crate::urls;
+
+mod entry;
 
 pub struct Cache {
     root: PathBuf,
     lock_file: PathBuf,
     locks_dir: PathBuf,
+    meta_dir: PathBuf,
     repos_dir: PathBuf,
+    config: Config,
 }
 
 impl Cache {
@@ -26,11 +37,21 @@ impl Cache {
         Self {
             lock_file: root.join(".LOCK"),
             locks_dir: root.join("locks"),
+            meta_dir: root.join("meta"),
             repos_dir: root.join("repos"),
             root,
+            config: Config::default(),
         }
     }
 
+    /// Uses the user [`Config`], which determines how the cached
+    /// repositories reach their remotes.
+    #[must_use]
+    pub fn with_config(mut self, config: &Config) -> Self {
+        self.config = config.clone();
+        self
+    }
+
     /// Initializes the cache folder, making sure it exists and contains the
     /// expected directory structure.
     ///
@@ -44,6 +65,9 @@ impl Cache {
         create_dir_all(&self.locks_dir)
             .map_err(|err| format_err!("cannot create locks directory: {err}"))?;
 
+        create_dir_all(&self.meta_dir)
+            .map_err(|err| format_err!("cannot create meta directory: {err}"))?;
+
         Ok(())
     }
 
@@ -91,14 +115,204 @@ impl Cache {
 
     /// Returns a [`Repository`] from the cache directory.
     ///
+    /// The repository is fetched from the dependency URL after applying the
+    /// rewrite rules of the [`Config`], but it is still cached under the
+    /// original URL, so mirrors share the cache entries.
+    ///
     /// # Errors
     ///
     /// This function will return an error if cannot open repository.
     pub fn get_repository(&self, dep: &Dependency) -> Result<Repository> {
         let path = self.get_repository_path(dep);
-        let repo = Repository::new(path);
-        repo.ensure(dep)
-            .map_err(|err| format_err!("cannot ensure repository: {err}"))
+        let url = self.config.rewrite_url(&dep.url);
+        if url != dep.url {
+            log::debug!("fetching {} from {url}", dep.url);
+        }
+
+        self.migrate_legacy_entry(dep)?;
+        let repo = Repository::new(path).with_config(&self.config);
+        if self.is_cached(dep) {
+            if let Err(err) = repo.set_origin_url(&url) {
+                log::debug!("cannot set origin of cached {}: {err}", dep.url);
+            }
+        }
+        let repo = repo
+            .ensure(dep, &url)
+            .map_err(|err| format_err!("cannot ensure repository: {err}"))?;
+        self.touch(dep)?;
+        Ok(repo)
+    }
+
+    /// Returns all the repositories stored in the cache, sorted from the most
+    /// recently used to the least recently used.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the repos directory cannot be
+    /// read.
+    pub fn entries(&self) -> Result<Vec<CacheEntry>> {
+        if !self.repos_dir.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut entries = vec![];
+        for dir_entry in fs::read_dir(&self.repos_dir)
+            .map_err(|err| format_err!("cannot read repos directory: {err}"))?
+        {
+            let dir_entry = dir_entry?;
+            if !dir_entry.path().is_dir() {
+                continue;
+            }
+            let key = dir_entry.file_name().to_string_lossy().to_string();
+            let meta = Metadata::load(self.meta_dir.join(&key)).ok();
+            entries.push(CacheEntry::new(key, dir_entry.path(), meta));
+        }
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.last_used));
+        Ok(entries)
+    }
+
+    /// Removes the repositories that have not been used for longer than
+    /// `max_age`. Then, if the cache still exceeds `max_size` bytes, removes
+    /// the least recently used repositories until it fits.
+    ///
+    /// Returns the entries that have been removed.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if some repository cannot be
+    /// removed.
+    pub fn gc(&self, max_age: Option<Duration>, max_size: Option<u64>) -> Result<Vec<CacheEntry>> {
+        let mut kept = self.entries()?;
+        let mut removed = vec![];
+
+        if let Some(max_age) = max_age {
+            let (expired, fresh): (Vec<_>, Vec<_>) =
+                kept.into_iter().partition(|entry| entry.age() > max_age);
+            removed.extend(expired);
+            kept = fresh;
+        }
+
+        if let Some(max_size) = max_size {
+            let mut total: u64 = kept.iter().map(|entry| entry.size).sum();
+            while total > max_size {
+                match kept.pop() {
+                    Some(entry) => {
+                        total -= entry.size;
+                        removed.push(entry);
+                    }
+                    None => break,
+                }
+            }
+        }
+
+        for entry in &removed {
+            self.remove_entry(entry)?;
+        }
+        Ok(removed)
+    }
+
+    /// Repacks every repository in the cache, see [`Repository::repack`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the cache cannot be listed.
+    pub fn repack(&self) -> Result<()> {
+        for entry in self.entries()? {
+            let name = entry.url.as_deref().unwrap_or(&entry.key);
+            log::info!("repacking {name}...");
+            if let Err(err) = Repository::new(&entry.path).repack() {
+                log::warn!("cannot repack {name}: {err}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Validates the integrity of every repository in the cache, see
+    /// [`Repository::validate`].
+    ///
+    /// Returns the entries that are broken, together with the reason.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the cache cannot be listed.
+    pub fn doctor(&self) -> Result<Vec<(CacheEntry, anyhow::Error)>> {
+        let mut broken = vec![];
+        for entry in self.entries()? {
+            let result = match &entry.url {
+                Some(url) => {
+                    Repository::new(&entry.path).validate(&self.config.rewrite_url(url), true)
+                }
+                None => Err(format_err!("missing cache metadata")),
+            };
+            if let Err(err) = result {
+                broken.push((entry, err));
+            }
+        }
+        Ok(broken)
+    }
+
+    /// Removes a repository from the cache, it will be cloned again the
+    /// next time it is used.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the repository cannot be
+    /// removed.
+    pub fn remove_entry(&self, entry: &CacheEntry) -> Result<()> {
+        remove_dir_all(&entry.path).map_err(|err| {
+            format_err!(
+                "cannot remove cached repository {path}: {err}",
+                path = entry.path.display()
+            )
+        })?;
+        for path in [
+            self.meta_dir.join(&entry.key),
+            self.locks_dir.join(&entry.key),
+        ] {
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves the repository cached under the legacy key of the dependency,
+    /// see [`legacy_url_md5`], to its current key, so it is not cloned again.
+    fn migrate_legacy_entry(&self, dep: &Dependency) -> Result<()> {
+        let (legacy, key) = (legacy_url_md5(dep), url_md5(dep));
+        let from = self.repos_dir.join(&legacy);
+        let to = self.repos_dir.join(&key);
+        if legacy == key || !from.exists() || to.exists() {
+            return Ok(());
+        }
+
+        log::debug!("migrating cached repository of {} to {key}", dep.url);
+        fs::rename(&from, &to)
+            .map_err(|err| format_err!("cannot migrate cached repository: {err}"))?;
+        let meta = self.meta_dir.join(&legacy);
+        if meta.exists() {
+            fs::rename(meta, self.meta_dir.join(&key))?;
+        }
+        let lock = self.locks_dir.join(&legacy);
+        if lock.exists() {
+            fs::remove_file(lock)?;
+        }
+        Ok(())
+    }
+
+    /// Returns whether the cache already holds a repository that was
+    /// cloned for the dependency URL, or an equivalent spelling of it.
+    fn is_cached(&self, dep: &Dependency) -> bool {
+        self.get_repository_path(dep).exists()
+            && Metadata::load(self.meta_dir.join(url_md5(dep)))
+                .map(|meta| urls::same(&meta.url, &dep.url))
+                .unwrap_or(false)
+    }
+
+    fn touch(&self, dep: &Dependency) -> Result<()> {
+        Metadata::new(&dep.url)
+            .save(self.meta_dir.join(url_md5(dep)))
+            .map_err(|err| format_err!("cannot update cache metadata: {err}"))
     }
 
     fn get_repository_path(&self, dep: &Dependency) -> PathBuf {
@@ -110,15 +324,33 @@ impl Cache {
     }
 }
 
+/// Returns the cache key of the dependency, equivalent URLs share it, see
+/// [`urls::same`].
 fn url_md5(dep: &Dependency) -> String {
+    format!("{:x}", sha2::Sha256::digest(urls::normalize(&dep.url)))
+}
+
+/// Returns the cache key the dependency had before keys were normalized,
+/// the hash of its URL as written.
+fn legacy_url_md5(dep: &Dependency) -> String {
     format!("{:x}", sha2::Sha256::digest(&dep.url))
 }
 
 #[cfg(test)]
 mod tests {
 
+    use std::path::Path;
+    use std::time::SystemTime;
+    use std::time::UNIX_EPOCH;
+
     use super::*;
+    use crate::config::UrlRewrite;
+    use crate::svec;
     use crate::test_utils::build_preset;
+    use crate::test_utils::commit_file;
+    use crate::test_utils::init_upstream;
+    use crate::test_utils::tempdir;
+    use crate::test_utils::write_to;
     use crate::test_utils::TestContext;
 
     #[test]
@@ -130,6 +362,7 @@ mod tests {
         let root: &PathBuf = &context.preset.cache().into();
         let repos = root.join("repos");
         let locks = root.join("locks");
+        let meta = root.join("meta");
 
         sut.clear();
         assert!(!root.exists());
@@ -138,6 +371,7 @@ mod tests {
         assert!(root.exists());
         assert!(repos.exists());
         assert!(locks.exists());
+        assert!(meta.exists());
 
         sut.clear();
         assert!(!root.exists());
@@ -156,6 +390,25 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_cache_get_repository_path_equivalent_urls() {
+        let sut = Cache::new(&build_preset());
+        let path = |url: &str| sut.get_repository_path(&Dependency::new(url, "master"));
+
+        assert_eq!(
+            path("https://github.com/a/b"),
+            path("git@github.com:a/b.git")
+        );
+        assert_eq!(
+            path("https://github.com/a/b"),
+            path("ssh://git@github.com/a/b/")
+        );
+        assert_ne!(
+            path("https://github.com/a/b"),
+            path("https://github.com/a/c")
+        );
+    }
+
     #[test]
     fn test_cache_get_repository_lock_path() {
         let preset = &build_preset();
@@ -168,4 +421,165 @@ mod tests {
             sut.get_repository_lock_path(dep).as_os_str()
         );
     }
+
+    fn add_entry(sut: &Cache, url: &str, last_used: u64, size: usize) -> Result<String> {
+        let dep = &Dependency::new(url, "some-branch");
+        let path = sut.get_repository_path(dep);
+        fs::create_dir_all(&path)?;
+        write_to(path.join("data"), &"x".repeat(size));
+        write_to(sut.get_repository_lock_path(dep), "");
+
+        let key = url_md5(dep);
+        Metadata {
+            url: url.into(),
+            last_used,
+        }
+        .save(sut.meta_dir.join(&key))?;
+        Ok(key)
+    }
+
+    #[test]
+    fn test_cache_entries_sorted_by_last_used() -> Result<()> {
+        let context = &TestContext::new();
+        let sut = Cache::new(&context.preset);
+        sut.initialize()?;
+
+        add_entry(&sut, "url-old", 10, 1)?;
+        add_entry(&sut, "url-new", 30, 2)?;
+        add_entry(&sut, "url-mid", 20, 3)?;
+
+        let urls: Vec<_> = sut.entries()?.into_iter().filter_map(|e| e.url).collect();
+        assert_eq!(vec!["url-new", "url-mid", "url-old"], urls);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_gc_max_age() -> Result<()> {
+        let context = &TestContext::new();
+        let sut = Cache::new(&context.preset);
+        sut.initialize()?;
+
+        let old = add_entry(&sut, "url-old", 10, 1)?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        add_entry(&sut, "url-new", now, 1)?;
+
+        let removed = sut.gc(Some(Duration::from_secs(3600)), None)?;
+
+        assert_eq!(1, removed.len());
+        assert_eq!(Some("url-old".to_string()), removed[0].url);
+        assert!(!sut.repos_dir.join(&old).exists());
+        assert!(!sut.meta_dir.join(&old).exists());
+        assert!(!sut.locks_dir.join(&old).exists());
+        assert_eq!(1, sut.entries()?.len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_gc_max_size_removes_least_recently_used() -> Result<()> {
+        let context = &TestContext::new();
+        let sut = Cache::new(&context.preset);
+        sut.initialize()?;
+
+        add_entry(&sut, "url-a", 10, 100)?;
+        add_entry(&sut, "url-b", 20, 100)?;
+        add_entry(&sut, "url-c", 30, 100)?;
+
+        let removed = sut.gc(None, Some(250))?;
+
+        let removed: Vec<_> = removed.into_iter().filter_map(|e| e.url).collect();
+        assert_eq!(vec!["url-a"], removed);
+        assert_eq!(2, sut.entries()?.len());
+        Ok(())
+    }
+
+    fn mirror_config(mirror: &Path) -> Config {
+        Config {
+            url_rewrites: vec![UrlRewrite {
+                base: format!("{}/", mirror.display()),
+                instead_of: svec!["https://example.invalid/"],
+            }],
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn test_cache_get_repository_applies_url_rewrites() -> Result<()> {
+        let context = &TestContext::new();
+        let mirrors = tempdir();
+        let (mirror_a, mirror_b) = (mirrors.path().join("a"), mirrors.path().join("b"));
+        commit_file(&init_upstream(mirror_a.join("repo")), "file.txt", "a");
+        commit_file(&init_upstream(mirror_b.join("repo")), "file.txt", "b");
+        let dep = &Dependency::new("https://example.invalid/repo", "master");
+
+        let sut = Cache::new(&context.preset).with_config(&mirror_config(&mirror_a));
+        sut.initialize()?;
+        let repo = sut.get_repository(dep)?;
+        write_to(repo.path().join("marker"), "");
+
+        let entries = sut.entries()?;
+        assert_eq!(1, entries.len());
+        assert_eq!(Some(dep.url.clone()), entries[0].url);
+
+        let sut = Cache::new(&context.preset).with_config(&mirror_config(&mirror_b));
+        let repo = sut.get_repository(dep)?;
+        assert!(repo.path().join("marker").exists(), "should not re-clone");
+        repo.validate(&mirror_b.join("repo").to_string_lossy(), false)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_get_repository_path_ignores_host_case() {
+        let sut = Cache::new(&build_preset());
+        let path = |url: &str| sut.get_repository_path(&Dependency::new(url, "master"));
+
+        assert_eq!(
+            path("https://GitHub.com/a/B"),
+            path("git@github.com:a/B.git")
+        );
+        assert_ne!(path("/srv/Repo"), path("/srv/repo"));
+    }
+
+    #[test]
+    fn test_cache_get_repository_migrates_legacy_entries() -> Result<()> {
+        let context = &TestContext::new();
+        let upstream = tempdir();
+        commit_file(&init_upstream(upstream.path()), "file.txt", "data");
+        let url = format!("{}/", upstream.path().display());
+        let dep = &Dependency::new(&url, "master");
+        let sut = Cache::new(&context.preset);
+        sut.initialize()?;
+        let repo = sut.get_repository(dep)?;
+        write_to(repo.path().join("marker"), "");
+        let legacy = sut.repos_dir.join(legacy_url_md5(dep));
+        fs::rename(repo.path(), &legacy)?;
+        fs::rename(
+            sut.meta_dir.join(url_md5(dep)),
+            sut.meta_dir.join(legacy_url_md5(dep)),
+        )?;
+
+        let repo = sut.get_repository(dep)?;
+
+        assert!(repo.path().join("marker").exists(), "should not re-clone");
+        assert!(!legacy.exists());
+        assert_eq!(1, sut.entries()?.len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_get_repository_heals_missing_origin() -> Result<()> {
+        let context = &TestContext::new();
+        let upstream = tempdir();
+        commit_file(&init_upstream(upstream.path()), "file.txt", "data");
+        let url = upstream.path().to_string_lossy().to_string();
+        let dep = &Dependency::new(&url, "master");
+        let sut = Cache::new(&context.preset);
+        sut.initialize()?;
+
+        let repo = sut.get_repository(dep)?;
+        git2::Repository::open(repo.path())?.remote_delete("origin")?;
+
+        let repo = sut.get_repository(dep)?;
+        repo.validate(&url, false)?;
+        Ok(())
+    }
 }
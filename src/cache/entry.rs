@@ -0,0 +1,166 @@
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::yaml;
+
+/// Metadata kept next to every cached repository, it allows to know which
+/// URL the repository was cloned from and when it was last used.
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Metadata {
+    pub url: String,
+
+    /// Seconds since the unix epoch of the last time the repository was used.
+    pub last_used: u64,
+}
+
+/// Represents a repository stored in the cache.
+#[derive(Debug)]
+pub struct CacheEntry {
+    pub key: String,
+    pub path: PathBuf,
+    pub url: Option<String>,
+    pub last_used: SystemTime,
+    pub size: u64,
+}
+
+impl Metadata {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            last_used: now(),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        yaml::load(path)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        yaml::save(self, path)
+    }
+}
+
+impl CacheEntry {
+    /// Builds a [`CacheEntry`] for the repository stored in `path`. When no
+    /// metadata is available, the modification time of the repository folder
+    /// is used as last use.
+    pub fn new(key: impl Into<String>, path: PathBuf, meta: Option<Metadata>) -> Self {
+        let last_used = match &meta {
+            Some(meta) => UNIX_EPOCH + Duration::from_secs(meta.last_used),
+            None => fs::metadata(&path)
+                .and_then(|m| m.modified())
+                .unwrap_or(UNIX_EPOCH),
+        };
+        Self {
+            key: key.into(),
+            size: dir_size(&path),
+            url: meta.map(|meta| meta.url),
+            last_used,
+            path,
+        }
+    }
+
+    /// Returns how long ago the repository was used.
+    pub fn age(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(self.last_used)
+            .unwrap_or_default()
+    }
+}
+
+/// Renders a byte count in a human friendly form, eg. `1.5 MiB`.
+pub fn human_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+    if size < 1024 {
+        return format!("{size} B");
+    }
+    #[allow(clippy::cast_precision_loss)]
+    let mut value = size as f64 / 1024.0;
+    let mut unit = UNITS[0];
+    for next in &UNITS[1..] {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = next;
+    }
+    format!("{value:.1} {unit}")
+}
+
+/// Renders a duration in a coarse human friendly form, eg. `3d ago`.
+pub fn human_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0..=59 => "just now".into(),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn dir_size<P: AsRef<Path>>(path: P) -> u64 {
+    walkdir::WalkDir::new(path)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(fs::Metadata::is_file)
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_utils::tempdir;
+    use crate::test_utils::write_to;
+
+    #[test]
+    fn test_human_size() {
+        assert_eq!("512 B", human_size(512));
+        assert_eq!("1.0 KiB", human_size(1024));
+        assert_eq!("1.5 MiB", human_size(1024 * 1024 * 3 / 2));
+        assert_eq!("2.0 GiB", human_size(2 * 1024 * 1024 * 1024));
+    }
+
+    #[test]
+    fn test_human_age() {
+        assert_eq!("just now", human_age(Duration::from_secs(5)));
+        assert_eq!("2m ago", human_age(Duration::from_secs(120)));
+        assert_eq!("3h ago", human_age(Duration::from_secs(3 * 3600)));
+        assert_eq!("4d ago", human_age(Duration::from_secs(4 * 86400)));
+    }
+
+    #[test]
+    fn test_cache_entry_uses_metadata() {
+        let root = tempdir();
+        write_to(root.path().join("a"), "12345");
+        write_to(root.path().join("b"), "123");
+
+        let meta = Metadata {
+            url: "some-url".into(),
+            last_used: 10,
+        };
+        let sut = CacheEntry::new("key", root.path().to_owned(), Some(meta));
+
+        assert_eq!(8, sut.size);
+        assert_eq!(Some("some-url".to_string()), sut.url);
+        assert_eq!(UNIX_EPOCH + Duration::from_secs(10), sut.last_used);
+    }
+}
@@ -6,9 +6,13 @@ use simplelog::LevelFilter;
 use simplelog::TermLogger;
 use simplelog::TerminalMode;
 
+use self::structs::CacheCommands;
 use self::structs::Cli;
 use self::structs::Commands;
+use self::structs::PatchCommands;
+use crate::config::Config;
 use crate::control::Controller;
+use crate::installer::LocalChanges;
 use crate::preset::Preset;
 
 mod structs;
@@ -23,23 +27,58 @@ pub fn run() -> Result<()> {
     setup_logging(cli.debug);
 
     let preset = Preset::new();
-    let controller = Controller::new(preset);
+    let config = Config::load()?;
+    let controller = Controller::new(preset, config);
     match cli.command {
         Commands::Init {} => controller.init(),
         Commands::Add {
             url,
             refname,
+            name,
             extensions,
             targets,
             ignores,
-        } => controller.add(&url, &refname, extensions, targets, ignores),
-        Commands::Install {} => controller.install()?,
-        Commands::Update {} => controller.update()?,
+            submodules,
+        } => controller.add(
+            &url, &refname, name, extensions, targets, ignores, submodules,
+        ),
+        Commands::Install {
+            frozen,
+            force,
+            backup,
+        } => controller.install(frozen, local_changes(force, backup))?,
+        Commands::Update { force, backup } => controller.update(local_changes(force, backup))?,
+        Commands::Lint { fix } => controller.lint(fix)?,
+        Commands::Status {} => controller.status()?,
+        Commands::Outdated {} => controller.outdated()?,
+        Commands::Verify {} => controller.verify()?,
+        Commands::Sbom { format, output } => controller.sbom(format, output)?,
+        Commands::Patch { command } => match command {
+            PatchCommands::Create { dep, output } => controller.patch_create(&dep, output)?,
+        },
         Commands::ClearCache {} => controller.clear_cache()?,
+        Commands::Cache { command } => match command {
+            CacheCommands::List {} => controller.cache_list()?,
+            CacheCommands::Gc {
+                max_age_days,
+                max_size_mb,
+                repack,
+            } => controller.cache_gc(max_age_days, max_size_mb, repack)?,
+            CacheCommands::Doctor { fix } => controller.cache_doctor(fix)?,
+        },
     };
     Ok(())
 }
 
+/// Backing up the modified files takes precedence over discarding them.
+fn local_changes(force: bool, backup: Option<String>) -> LocalChanges {
+    match backup {
+        Some(backup) => LocalChanges::Backup(backup.into()),
+        None if force => LocalChanges::Discard,
+        None => LocalChanges::Refuse,
+    }
+}
+
 fn setup_logging(is_debug: bool) {
     let logging_config = ConfigBuilder::default()
         .set_time_level(LevelFilter::Off)
@@ -1,6 +1,8 @@
 use clap::Parser;
 use clap::Subcommand;
 
+use crate::sbom;
+
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
 #[clap(propagate_version = true)]
@@ -28,6 +30,10 @@ pub enum Commands {
         #[clap(default_value = "master")]
         refname: String,
 
+        /// Unique name of the dependency, defaults to the repository name
+        #[clap(short, long)]
+        name: Option<String>,
+
         /// Extensions to vendor
         #[clap(short, long)]
         extensions: Option<Vec<String>>,
@@ -39,14 +45,127 @@ pub enum Commands {
         /// Ignored paths that will NOT be vendored
         #[clap(short, long)]
         ignores: Option<Vec<String>>,
+
+        /// Check out submodules recursively before vendoring
+        #[clap(long, takes_value = false, parse(from_flag))]
+        submodules: bool,
     },
 
     /// Vendors the dependencies respecting the lock pins
-    Install {},
+    Install {
+        /// Fail when the spec has drifted from the lock, and never write
+        /// the spec nor the lock
+        #[clap(long, takes_value = false, parse(from_flag))]
+        frozen: bool,
+
+        /// Overwrite the vendored files that were modified locally
+        #[clap(long, takes_value = false, parse(from_flag))]
+        force: bool,
+
+        /// Copy the vendored files that were modified locally into this
+        /// folder, before overwriting them
+        #[clap(long)]
+        backup: Option<String>,
+    },
 
     /// Updates the vendored dependencies according to the desired refname
     /// in the spec file, updates the pins in the lock file.
-    Update {},
+    Update {
+        /// Overwrite the vendored files that were modified locally
+        #[clap(long, takes_value = false, parse(from_flag))]
+        force: bool,
 
+        /// Copy the vendored files that were modified locally into this
+        /// folder, before overwriting them
+        #[clap(long)]
+        backup: Option<String>,
+    },
+
+    /// Validates the spec file, reporting every problem found
+    Lint {
+        /// Fix the mechanical problems, like leading dots in extensions
+        #[clap(long, takes_value = false, parse(from_flag))]
+        fix: bool,
+    },
+
+    /// Shows the locked, wanted and latest version of every dependency
+    Status {},
+
+    /// Lists the dependencies that an update would change
+    Outdated {},
+
+    /// Checks the vendored files against the manifest, ignoring their
+    /// provenance headers
+    Verify {},
+
+    /// Exports a software bill of materials of the vendored dependencies,
+    /// built offline from the spec, the lock and the vendor folder
+    Sbom {
+        /// Format of the bill of materials
+        #[clap(short, long, value_enum, default_value = "cyclonedx-json")]
+        format: sbom::Format,
+
+        /// File to write the bill of materials to, instead of the standard
+        /// output
+        #[clap(short, long)]
+        output: Option<String>,
+    },
+
+    /// Manage the local patches applied on top of dependencies
+    Patch {
+        #[clap(subcommand)]
+        command: PatchCommands,
+    },
+
+    /// Removes the whole cache directory
     ClearCache {},
+
+    /// Inspect and maintain the repositories cache
+    Cache {
+        #[clap(subcommand)]
+        command: CacheCommands,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum PatchCommands {
+    /// Create a patch from the local modifications made to the vendored
+    /// files of a dependency
+    Create {
+        /// Name or Git URL of the vendored dependency
+        dep: String,
+
+        /// File to write the patch to, instead of the standard output
+        #[clap(short, long)]
+        output: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum CacheCommands {
+    /// List the cached repositories, with their size and last use
+    List {},
+
+    /// Remove cached repositories that are stale or exceed the size limit
+    Gc {
+        /// Remove repositories not used within this amount of days
+        #[clap(long)]
+        max_age_days: Option<u64>,
+
+        /// Remove least recently used repositories until the cache fits
+        /// within this amount of megabytes
+        #[clap(long)]
+        max_size_mb: Option<u64>,
+
+        /// Repack the remaining repositories with `git gc`
+        #[clap(long, takes_value = false, parse(from_flag))]
+        repack: bool,
+    },
+
+    /// Check the integrity of every cached repository
+    Doctor {
+        /// Remove the broken repositories, so they are cloned again on use
+        #[clap(long, takes_value = false, parse(from_flag))]
+        fix: bool,
+    },
 }
@@ -0,0 +1,286 @@
+use std::env;
+use std::path::PathBuf;
+
+use anyhow::format_err;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::deps::is_default;
+use crate::deps::is_false;
+use crate::yaml;
+
+/// Environment variable that overrides the location of the user config.
+const CONFIG_ENV: &str = "VENDIFY_CONFIG";
+
+/// Environment variable with extra URL rewrite rules, formatted as a list of
+/// `base=instead_of` pairs separated by `;`.
+const URL_REWRITES_ENV: &str = "VENDIFY_URL_REWRITES";
+
+/// User level configuration. Unlike the spec, it is not meant to be
+/// committed, as it describes the environment vendify runs in.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Config {
+    /// Rules to rewrite dependency URLs before any network access.
+    #[serde(default, skip_serializing_if = "<[_]>::is_empty")]
+    pub url_rewrites: Vec<UrlRewrite>,
+
+    /// Local Git LFS object store, used to resolve the LFS pointer files
+    /// found in dependencies. It has the layout of `.git/lfs/objects`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lfs_store: Option<PathBuf>,
+
+    /// Credentials used for the remotes of each host. Hosts without them
+    /// use the git configuration, see `git2_credentials`.
+    #[serde(default, skip_serializing_if = "<[_]>::is_empty")]
+    pub credentials: Vec<Credentials>,
+
+    /// Proxy for the HTTP remotes, eg. `http://proxy.corp:3128`. It takes
+    /// precedence over `HTTPS_PROXY`, `HTTP_PROXY` and git's `http.proxy`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+
+    /// Hosts reached without proxy, on top of the ones in `NO_PROXY`. Parent
+    /// domains cover their subdomains.
+    #[serde(default, skip_serializing_if = "<[_]>::is_empty")]
+    pub no_proxy: Vec<String>,
+
+    /// PEM bundle of the certificate authorities trusted for HTTPS remotes,
+    /// on top of the ones of the system.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_bundle: Option<PathBuf>,
+
+    /// Skips the verification of the TLS certificates of the remotes, like
+    /// git's `http.sslVerify=false`.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub insecure_tls: bool,
+
+    /// Retries of the network operations that fail with transient errors.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub retry: Retry,
+
+    /// Seconds a network operation can take before it is abandoned, eg. a
+    /// clone from a server that stopped responding. Unbounded by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+}
+
+/// Exponential backoff between the attempts of a network operation.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Retry {
+    /// Attempts of each operation, the first one included.
+    pub attempts: u32,
+
+    /// Delay before the first retry, doubled after every other attempt.
+    pub backoff_ms: u64,
+
+    /// Maximum delay between two attempts.
+    pub max_backoff_ms: u64,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            backoff_ms: 500,
+            max_backoff_ms: 8000,
+        }
+    }
+}
+
+/// How to authenticate against the remotes of a host. The methods are tried
+/// in order, once each: the SSH agent, the SSH key and the token.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Credentials {
+    /// Host of the remotes, with the port when it is not the default one,
+    /// eg. `github.com` or `git.corp:8443`.
+    pub host: String,
+
+    /// User sent along the token, or the SSH key, when the URL has none.
+    /// Defaults to `git`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+
+    /// Environment variable holding the token used as password over HTTPS.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_env: Option<String>,
+
+    /// Private key used over SSH.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssh_key: Option<PathBuf>,
+
+    /// Environment variable holding the passphrase of the SSH key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssh_passphrase_env: Option<String>,
+
+    /// Whether to ask the SSH agent for the key.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub ssh_agent: bool,
+}
+
+/// Rewrites URLs starting with any of the `instead_of` prefixes, replacing
+/// the prefix with `base`. Works like git's `url.<base>.insteadOf`.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct UrlRewrite {
+    pub base: String,
+    pub instead_of: Vec<String>,
+}
+
+impl Config {
+    /// Loads the user configuration from `$VENDIFY_CONFIG`, or from
+    /// `$XDG_CONFIG_HOME/vendify/config.yml` when unset. A missing file
+    /// results in the default configuration. Rules from the environment take
+    /// precedence over the ones in the file.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the configuration file exists
+    /// but cannot be parsed, or the environment contains invalid rules.
+    pub fn load() -> Result<Self> {
+        let mut config = match Self::path() {
+            Some(path) if path.exists() => yaml::load(path)?,
+            _ => Self::default(),
+        };
+
+        if let Ok(rules) = env::var(URL_REWRITES_ENV) {
+            let mut rewrites = parse_url_rewrites(&rules)?;
+            rewrites.append(&mut config.url_rewrites);
+            config.url_rewrites = rewrites;
+        }
+
+        Ok(config)
+    }
+
+    /// Returns the URL that has to be used to fetch `url`, after applying
+    /// the rewrite rules. When several prefixes match, the longest wins.
+    pub fn rewrite_url(&self, url: &str) -> String {
+        let best = self
+            .url_rewrites
+            .iter()
+            .flat_map(|rewrite| {
+                rewrite
+                    .instead_of
+                    .iter()
+                    .map(move |prefix| (prefix, &rewrite.base))
+            })
+            .filter(|(prefix, _)| url.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len());
+
+        match best {
+            Some((prefix, base)) => format!("{base}{}", &url[prefix.len()..]),
+            None => url.to_string(),
+        }
+    }
+
+    /// Returns the credentials configured for the host.
+    pub fn credentials(&self, host: &str) -> Option<&Credentials> {
+        self.credentials
+            .iter()
+            .find(|credentials| credentials.host.eq_ignore_ascii_case(host))
+    }
+
+    fn path() -> Option<PathBuf> {
+        if let Ok(path) = env::var(CONFIG_ENV) {
+            return Some(path.into());
+        }
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| home::home_dir().map(|home| home.join(".config")))
+            .map(|dir| dir.join("vendify").join("config.yml"))
+    }
+}
+
+fn parse_url_rewrites(rules: &str) -> Result<Vec<UrlRewrite>> {
+    rules
+        .split(';')
+        .map(str::trim)
+        .filter(|rule| !rule.is_empty())
+        .map(|rule| match rule.split_once('=') {
+            Some((base, instead_of)) if !instead_of.is_empty() => Ok(UrlRewrite {
+                base: base.to_string(),
+                instead_of: vec![instead_of.to_string()],
+            }),
+            _ => Err(format_err!(
+                "invalid rule '{rule}' in {URL_REWRITES_ENV}, expected 'base=instead_of'"
+            )),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::svec;
+
+    fn build_config() -> Config {
+        Config {
+            url_rewrites: vec![
+                UrlRewrite {
+                    base: "https://mirror/github/".into(),
+                    instead_of: svec!["https://github.com/", "git@github.com:"],
+                },
+                UrlRewrite {
+                    base: "https://mirror/special/".into(),
+                    instead_of: svec!["https://github.com/special/"],
+                },
+            ],
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn test_config_rewrite_url() {
+        let sut = build_config();
+
+        assert_eq!(
+            "https://mirror/github/a/b",
+            sut.rewrite_url("https://github.com/a/b")
+        );
+        assert_eq!(
+            "https://mirror/github/a/b.git",
+            sut.rewrite_url("git@github.com:a/b.git")
+        );
+        assert_eq!(
+            "https://gitlab.com/a/b",
+            sut.rewrite_url("https://gitlab.com/a/b")
+        );
+    }
+
+    #[test]
+    fn test_config_rewrite_url_longest_prefix_wins() {
+        let sut = build_config();
+
+        assert_eq!(
+            "https://mirror/special/repo",
+            sut.rewrite_url("https://github.com/special/repo")
+        );
+    }
+
+    #[test]
+    fn test_parse_url_rewrites() -> Result<()> {
+        let actual = parse_url_rewrites("https://a/=https://b/; https://c/=git@d:;")?;
+
+        assert_eq!(
+            vec![
+                UrlRewrite {
+                    base: "https://a/".into(),
+                    instead_of: svec!["https://b/"],
+                },
+                UrlRewrite {
+                    base: "https://c/".into(),
+                    instead_of: svec!["git@d:"],
+                },
+            ],
+            actual
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_url_rewrites_invalid() {
+        assert!(parse_url_rewrites("https://a/").is_err());
+        assert!(parse_url_rewrites("https://a/=").is_err());
+    }
+}
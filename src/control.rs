@@ -1,22 +1,34 @@
+use std::fs;
+use std::path::Path;
 use std::path::PathBuf;
+use std::time::Duration;
 
+use anyhow::format_err;
 use anyhow::Result;
 
 use super::deps::Dependency;
+use super::installer::manifest::Manifest;
 use super::installer::Installer;
+use super::installer::LocalChanges;
 use super::spec::Spec;
 use super::spec_lock::SpecLock;
+use crate::cache::human_age;
+use crate::cache::human_size;
 use crate::cache::Cache;
+use crate::config::Config;
 use crate::filters::FilterKind;
 use crate::preset::Preset;
+use crate::sbom;
+use crate::status::Status;
 
 pub struct Controller {
     preset: Preset,
+    config: Config,
 }
 
 impl Controller {
-    pub fn new(preset: Preset) -> Self {
-        Self { preset }
+    pub fn new(preset: Preset, config: Config) -> Self {
+        Self { preset, config }
     }
 
     pub fn init(&self) {
@@ -37,13 +49,16 @@ impl Controller {
         log::info!("{} has been created", spec_path.display());
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn add(
         &self,
         url: &str,
         refname: &str,
+        name: Option<String>,
         extensions: Option<Vec<String>>,
         targets: Option<Vec<String>>,
         ignores: Option<Vec<String>>,
+        submodules: bool,
     ) {
         let mut spec = match Spec::load_from(&self.preset) {
             Ok(spec) => spec,
@@ -54,6 +69,7 @@ impl Controller {
         };
 
         let mut dep = Dependency::new(url, refname);
+        dep.name = name;
         if let Some(extensions) = extensions {
             dep.filters.add(FilterKind::Extension(extensions));
         }
@@ -63,11 +79,24 @@ impl Controller {
         if let Some(ignores) = ignores {
             dep.filters.add(FilterKind::Ignore(ignores));
         }
-        spec.add_dependency(dep);
+        dep.submodules = submodules;
+        let name = dep.name();
 
-        match spec.save() {
+        let violations = spec.policy_violations(&dep);
+        if !violations.is_empty() {
+            for violation in &violations {
+                log::error!("{violation}");
+            }
+            log::error!(
+                "cannot add dependency: {} policy violations found",
+                violations.len()
+            );
+            return;
+        }
+
+        match spec.add_dependency(dep).and_then(|()| spec.save()) {
             Ok(_) => {
-                log::info!("added dependency {url}@{refname}");
+                log::info!("added dependency {name} {url}@{refname}");
             }
             Err(err) => {
                 log::error!("cannot add dependency: {err}");
@@ -75,16 +104,21 @@ impl Controller {
         }
     }
 
-    pub fn install(&self) -> Result<()> {
+    pub fn install(&self, frozen: bool, local_changes: LocalChanges) -> Result<()> {
         let (mut spec, spec_lock) = self.load_both()?;
-        let cache = Cache::new(&self.preset);
+        let cache = self.cache();
         let _cache_lock = cache.lock();
-        let installer = Installer::new(cache, &spec, spec_lock);
+        let installer = Installer::new(cache, &spec, spec_lock)
+            .with_config(&self.config)
+            .with_frozen(frozen)
+            .with_local_changes(local_changes);
 
         if let Err(err) = {
             let mut spec_lock = installer.install()?;
-            spec_lock.save()?;
-            spec.save()?;
+            if !frozen {
+                spec_lock.save()?;
+                spec.save()?;
+            }
             Ok(())
         } {
             log::error!("install failed: {err}");
@@ -95,11 +129,13 @@ impl Controller {
         Ok(())
     }
 
-    pub fn update(&self) -> Result<()> {
+    pub fn update(&self, local_changes: LocalChanges) -> Result<()> {
         let (mut spec, spec_lock) = self.load_both()?;
-        let cache = Cache::new(&self.preset);
+        let cache = self.cache();
         let _cache_lock = cache.lock();
-        let installer = Installer::new(cache, &spec, spec_lock);
+        let installer = Installer::new(cache, &spec, spec_lock)
+            .with_config(&self.config)
+            .with_local_changes(local_changes);
 
         if let Err(err) = {
             let mut spec_lock = installer.update()?;
@@ -115,8 +151,214 @@ impl Controller {
         Ok(())
     }
 
+    pub fn patch_create(&self, handle: &str, output: Option<String>) -> Result<()> {
+        let (spec, spec_lock) = self.load_both()?;
+        let dep = spec.find_dependency(handle)?;
+        let cache = self.cache();
+        let _cache_lock = cache.lock()?;
+        let installer = Installer::new(cache, &spec, spec_lock).with_config(&self.config);
+
+        let diff = installer.create_patch(dep)?;
+        if diff.is_empty() {
+            log::warn!("no local modifications found for {handle}");
+            return Ok(());
+        }
+        match output {
+            Some(path) => {
+                fs::write(&path, diff)?;
+                log::info!("patch written to {path}, add it to the dependency patches");
+            }
+            None => print!("{diff}"),
+        }
+        Ok(())
+    }
+
+    pub fn lint(&self, fix: bool) -> Result<()> {
+        let mut spec = Spec::load_from(&self.preset)?;
+        if fix {
+            let fixed = spec.fix_problems();
+            for problem in &fixed {
+                log::info!("fixed {problem}");
+            }
+            if !fixed.is_empty() {
+                spec.save()?;
+            }
+        }
+
+        let problems = spec.problems();
+        for problem in &problems {
+            log::error!("{problem}");
+        }
+        if problems.is_empty() {
+            log::info!("lint success ✅");
+            Ok(())
+        } else {
+            Err(format_err!("{} problems found in the spec", problems.len()))
+        }
+    }
+
+    pub fn status(&self) -> Result<()> {
+        for status in self.check_status()? {
+            log::info!(
+                "{name}@{refname}\t{current} -> {wanted}\t(latest {latest}){outdated}",
+                name = status.name,
+                refname = status.refname,
+                current = status.current.as_deref().unwrap_or("-"),
+                wanted = status.wanted.as_deref().unwrap_or("-"),
+                latest = status.latest.as_deref().unwrap_or("-"),
+                outdated = if status.is_outdated() {
+                    " outdated"
+                } else {
+                    ""
+                },
+            );
+        }
+        Ok(())
+    }
+
+    pub fn outdated(&self) -> Result<()> {
+        let outdated: Vec<_> = self
+            .check_status()?
+            .into_iter()
+            .filter(Status::is_outdated)
+            .collect();
+        for status in &outdated {
+            log::warn!(
+                "{name}@{refname}\t{current} -> {wanted}\t(latest {latest})",
+                name = status.name,
+                refname = status.refname,
+                current = status.current.as_deref().unwrap_or("-"),
+                wanted = status.wanted.as_deref().unwrap_or("-"),
+                latest = status.latest.as_deref().unwrap_or("-"),
+            );
+        }
+        if outdated.is_empty() {
+            log::info!("all dependencies are up to date ✅");
+        }
+        Ok(())
+    }
+
+    pub fn verify(&self) -> Result<()> {
+        let spec = Spec::load_from(&self.preset)?;
+        let manifest = Manifest::load(Path::new(&spec.vendor))?;
+        let mismatches = manifest.verify(Path::new(&spec.vendor));
+        for mismatch in &mismatches {
+            log::error!("{mismatch}");
+        }
+        if mismatches.is_empty() {
+            log::info!("verify success ✅");
+            Ok(())
+        } else {
+            Err(format_err!(
+                "{} vendored files do not match the manifest",
+                mismatches.len()
+            ))
+        }
+    }
+
+    pub fn sbom(&self, format: sbom::Format, output: Option<String>) -> Result<()> {
+        let (spec, spec_lock) = self.load_both()?;
+        let document = serde_json::to_string_pretty(&sbom::generate(&spec, &spec_lock, format)?)?;
+        match output {
+            Some(path) => {
+                fs::write(&path, document + "\n")?;
+                log::info!("sbom written to {path}");
+            }
+            None => println!("{document}"),
+        }
+        Ok(())
+    }
+
     pub fn clear_cache(&self) -> Result<()> {
-        Cache::new(&self.preset).clear()
+        self.cache().clear()
+    }
+
+    pub fn cache_list(&self) -> Result<()> {
+        let cache = self.cache();
+        let _cache_lock = cache.lock()?;
+
+        let entries = cache.entries()?;
+        for entry in &entries {
+            log::info!(
+                "{url}\t{size}\t{age}",
+                url = entry.url.as_deref().unwrap_or(&entry.key),
+                size = human_size(entry.size),
+                age = human_age(entry.age()),
+            );
+        }
+        let total: u64 = entries.iter().map(|entry| entry.size).sum();
+        log::info!("{} repositories, {}", entries.len(), human_size(total));
+        Ok(())
+    }
+
+    pub fn cache_gc(
+        &self,
+        max_age_days: Option<u64>,
+        max_size_mb: Option<u64>,
+        repack: bool,
+    ) -> Result<()> {
+        let cache = self.cache();
+        let _cache_lock = cache.lock()?;
+
+        let max_age =
+            max_age_days.map(|days| Duration::from_secs(days.saturating_mul(24 * 60 * 60)));
+        let max_size = max_size_mb.map(|mb| mb.saturating_mul(1024 * 1024));
+        for entry in cache.gc(max_age, max_size)? {
+            log::info!(
+                "removed {url} ({size}, used {age})",
+                url = entry.url.as_deref().unwrap_or(&entry.key),
+                size = human_size(entry.size),
+                age = human_age(entry.age()),
+            );
+        }
+        if repack {
+            cache.repack()?;
+        }
+
+        log::info!("cache gc success ✅");
+        Ok(())
+    }
+
+    pub fn cache_doctor(&self, fix: bool) -> Result<()> {
+        let cache = self.cache();
+        let _cache_lock = cache.lock()?;
+
+        let broken = cache.doctor()?;
+        for (entry, err) in &broken {
+            let url = entry.url.as_deref().unwrap_or(&entry.key);
+            if fix {
+                cache.remove_entry(entry)?;
+                log::warn!("removed broken {url}: {err}");
+            } else {
+                log::error!("broken {url}: {err}");
+            }
+        }
+
+        if broken.is_empty() || fix {
+            log::info!("cache doctor success ✅");
+            Ok(())
+        } else {
+            Err(format_err!(
+                "{} broken repositories found, run with --fix to remove them",
+                broken.len()
+            ))
+        }
+    }
+
+    fn check_status(&self) -> Result<Vec<Status>> {
+        let (spec, spec_lock) = self.load_both()?;
+        let cache = self.cache();
+        let _cache_lock = cache.lock()?;
+        cache.initialize()?;
+
+        spec.deps
+            .iter()
+            .map(|dep| Status::check(&cache, dep, spec_lock.get_locked_dependency(&dep.name())))
+            .collect()
+    }
+
+    fn cache(&self) -> Cache {
+        Cache::new(&self.preset).with_config(&self.config)
     }
 
     fn load_both(&self) -> Result<(Spec, SpecLock)> {
@@ -128,10 +370,19 @@ impl Controller {
             }
         };
 
-        let spec_lock = match SpecLock::load_from(&self.preset) {
-            Ok(value) => value,
-            Err(_) => SpecLock::with_preset(&self.preset),
+        let spec_lock_path: &PathBuf = &self.preset.spec_lock().into();
+        let mut spec_lock = if spec_lock_path.exists() {
+            match SpecLock::load_from(&self.preset) {
+                Ok(value) => value,
+                Err(err) => {
+                    log::error!("{err}");
+                    return Err(err);
+                }
+            }
+        } else {
+            SpecLock::with_preset(&self.preset)
         };
+        spec_lock.adopt_names(&spec.deps);
 
         Ok((spec, spec_lock))
     }
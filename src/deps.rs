@@ -1,24 +1,138 @@
 use std::fmt::Debug;
 
+use semver::VersionReq;
 use serde::Deserialize;
 use serde::Serialize;
 
 use crate::filters::Filters;
 use crate::preset::Preset;
+use crate::signatures::SignaturePolicy;
+use crate::transforms::Normalize;
+use crate::transforms::Transform;
+use crate::versions;
 
 #[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Clone)]
 pub struct Dependency {
+    /// Unique name of the dependency, defaults to the name of the
+    /// repository, see [`name_from_url`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
     pub url: String,
+
+    /// A branch, tag or commit, or a semver range resolved against the tags
+    /// of the repository, eg. `^1.4`.
     pub refname: String,
 
+    /// Whether pre-release tags satisfy the semver range of the refname.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub prereleases: bool,
+
     #[serde(flatten)]
     pub filters: Filters,
+
+    /// Whether submodules are checked out, recursively, before vendoring.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub submodules: bool,
+
+    /// Transforms applied to the files while they are vendored.
+    #[serde(default, skip_serializing_if = "<[_]>::is_empty")]
+    pub transforms: Vec<Transform>,
+
+    /// Normalization of the text files, overrides the one of the spec.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub normalize: Option<Normalize>,
+
+    /// Commands run in the vendor directory once the dependency has been
+    /// vendored.
+    #[serde(default, skip_serializing_if = "<[_]>::is_empty")]
+    pub hooks: Vec<String>,
+
+    /// Unified diff files applied on top of the vendored files.
+    #[serde(default, skip_serializing_if = "<[_]>::is_empty")]
+    pub patches: Vec<String>,
+
+    /// How symbolic links are vendored, they are skipped by default.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub symlinks: Symlinks,
+
+    /// How the permissions of the vendored files are set, they follow the
+    /// git tree by default.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub modes: Modes,
+
+    /// Requires the tag the refname names, or the locked commit, to be
+    /// signed by one of the allowed keys.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify_signatures: Option<SignaturePolicy>,
+}
+
+/// How the symbolic links found in a dependency are vendored.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Symlinks {
+    /// Symbolic links are not vendored.
+    #[default]
+    Skip,
+
+    /// Symbolic links are vendored as such, as long as they point to a
+    /// path inside the dependency.
+    Preserve,
+
+    /// The target of symbolic links is vendored, as long as it is inside the
+    /// dependency.
+    Follow,
+}
+
+/// How the permissions of the vendored files are set.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Modes {
+    /// Files the git tree records as executable are vendored with 0755, the
+    /// rest with 0644.
+    #[default]
+    Git,
+
+    /// Every file is vendored with 0644.
+    Normalize,
 }
 
 #[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Clone)]
 pub struct LockedDependency {
+    /// Name of the locked dependency, see [`Dependency::name`].
+    pub name: String,
     pub url: String,
     pub refname: String,
+
+    /// Hash of the vendored contents, and of the transforms that produced
+    /// them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+
+    /// Tag the semver range of the dependency was resolved to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+
+    /// Fingerprint of the filters, preset and vendor folder the dependency
+    /// was vendored with, see [`crate::spec::Spec::fingerprint`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+
+    /// Fingerprint of the key that signed the locked revision, when the
+    /// dependency verifies signatures.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signer: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub submodules: Vec<LockedSubmodule>,
+}
+
+/// Commit a submodule was checked out at, the path is relative to the
+/// dependency repository root.
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Clone)]
+pub struct LockedSubmodule {
+    pub path: String,
+    pub commit: String,
 }
 
 impl Dependency {
@@ -26,20 +140,55 @@ impl Dependency {
     /// come pre-configured for working with proto files.
     pub fn new(url: impl Into<String>, refname: impl Into<String>) -> Self {
         Self {
+            name: None,
             url: url.into(),
             refname: refname.into(),
+            prereleases: false,
             filters: Filters::new(),
+            submodules: false,
+            transforms: vec![],
+            normalize: None,
+            hooks: vec![],
+            patches: vec![],
+            symlinks: Symlinks::default(),
+            modes: Modes::default(),
+            verify_signatures: None,
+        }
+    }
+
+    /// Returns the name of the dependency, which identifies it in the spec,
+    /// the lock and the command line.
+    pub fn name(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => name_from_url(&self.url),
         }
     }
 
+    /// Returns the semver range of the refname, when it is one.
+    pub fn version_req(&self) -> Option<VersionReq> {
+        versions::parse_range(&self.refname)
+    }
+
     pub fn to_locked_dependency(&self, refname: impl Into<String>) -> LockedDependency {
-        LockedDependency::new(&self.url, refname)
+        let mut locked = LockedDependency::new(&self.url, refname);
+        locked.name = self.name();
+        locked
     }
 
     /// Updates the values, taken from another dependency.
     pub fn update_from(&mut self, other: &Dependency) -> &Self {
         self.refname = other.refname.clone();
+        self.prereleases = other.prereleases;
         self.filters = other.filters.clone();
+        self.submodules = other.submodules;
+        self.transforms = other.transforms.clone();
+        self.normalize = other.normalize;
+        self.hooks = other.hooks.clone();
+        self.patches = other.patches.clone();
+        self.symlinks = other.symlinks;
+        self.modes = other.modes;
+        self.verify_signatures = other.verify_signatures.clone();
         self
     }
 
@@ -54,13 +203,40 @@ impl Dependency {
 
 impl LockedDependency {
     pub fn new(url: impl Into<String>, refname: impl Into<String>) -> Self {
+        let url = url.into();
         Self {
-            url: url.into(),
+            name: name_from_url(&url),
+            url,
             refname: refname.into(),
+            hash: None,
+            version: None,
+            fingerprint: None,
+            signer: None,
+            submodules: vec![],
         }
     }
 }
 
+/// Derives a dependency name from its URL, the last component of the path
+/// without the `.git` suffix, eg. `ledger` for `git@github.com:a/ledger.git`.
+pub fn name_from_url(url: &str) -> String {
+    let path = url.trim_end_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    match path.rsplit(['/', ':']).next() {
+        Some(name) if !name.is_empty() => name.to_string(),
+        _ => url.to_string(),
+    }
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+pub(crate) fn is_false(value: &bool) -> bool {
+    !value
+}
+
+pub(crate) fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -110,6 +286,25 @@ mod tests {
 
         assert_eq!(sut.url, locked.url);
         assert_eq!("other-refname", locked.refname);
+        assert_eq!("some-url", locked.name);
+    }
+
+    #[test]
+    fn test_dependency_name() {
+        let mut sut = Dependency::new("https://github.com/a/ledger.git", "master");
+        assert_eq!("ledger", sut.name());
+
+        sut.name = Some("ledger-v2".into());
+        assert_eq!("ledger-v2", sut.name());
+    }
+
+    #[test]
+    fn test_name_from_url() {
+        assert_eq!("b", name_from_url("https://github.com/a/b"));
+        assert_eq!("b", name_from_url("https://github.com/a/b.git/"));
+        assert_eq!("b", name_from_url("git@github.com:a/b.git"));
+        assert_eq!("b", name_from_url("git@github.com:b"));
+        assert_eq!("b", name_from_url("/tmp/b"));
     }
 
     #[test]
@@ -127,11 +322,13 @@ mod tests {
             .add(FilterKind::Extension(svec!["1"]))
             .add(FilterKind::Target(svec!["2"]))
             .add(FilterKind::Ignore(svec!["3"]));
+        other.submodules = true;
 
         let mut actual = original.clone();
         actual.update_from(&other);
 
         assert_eq!("refname-b", actual.refname);
         assert_eq!(actual.filters, other.filters);
+        assert!(actual.submodules);
     }
 }
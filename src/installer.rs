@@ -1,37 +1,96 @@
 use std::fs;
 use std::path::Path;
+use std::path::PathBuf;
 use std::thread;
 
 use anyhow::format_err;
 use anyhow::Result;
 
+use self::importer::Imported;
 use self::importer::Importer;
+use self::manifest::Manifest;
+use crate::atomic;
 use crate::cache::Cache;
+use crate::config::Config;
 use crate::deps::Dependency;
-use crate::deps::LockedDependency;
+use crate::policy::Vendored;
+use crate::policy::Violation;
 use crate::spec::Spec;
 use crate::spec_lock::SpecLock;
 
 mod collector;
+mod hooks;
 mod importer;
+mod lfs;
+mod licenses;
+pub mod manifest;
+mod patch;
 mod selector;
 
+/// What to do with the vendored files that were modified locally, which
+/// installing would overwrite.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub enum LocalChanges {
+    /// Refuse to install, listing the modified files.
+    #[default]
+    Refuse,
+
+    /// Overwrite the modified files.
+    Discard,
+
+    /// Copy the modified files into the folder, keeping their path relative
+    /// to the vendor folder, before overwriting them.
+    Backup(PathBuf),
+}
+
 pub struct Installer<'spec> {
     cache: Cache,
     spec: &'spec Spec,
     spec_lock: SpecLock,
+    staging: PathBuf,
+    config: Config,
+    frozen: bool,
+    local_changes: LocalChanges,
 }
 
 impl<'spec> Installer<'spec> {
     pub fn new(cache: Cache, spec: &'spec Spec, spec_lock: SpecLock) -> Self {
         Self {
             cache,
+            staging: atomic::sibling(&spec.vendor, "tmp"),
             spec,
             spec_lock,
+            config: Config::default(),
+            frozen: false,
+            local_changes: LocalChanges::default(),
         }
     }
 
+    /// Uses the user [`Config`] while importing the dependencies.
+    #[must_use]
+    pub fn with_config(mut self, config: &Config) -> Self {
+        self.config = config.clone();
+        self
+    }
+
+    /// Refuses to install when the lock does not match the spec, instead of
+    /// warning about it.
+    #[must_use]
+    pub fn with_frozen(mut self, frozen: bool) -> Self {
+        self.frozen = frozen;
+        self
+    }
+
+    /// Sets what happens to the vendored files that were modified locally,
+    /// by default installing is refused.
+    #[must_use]
+    pub fn with_local_changes(mut self, local_changes: LocalChanges) -> Self {
+        self.local_changes = local_changes;
+        self
+    }
+
     pub fn install(self) -> Result<SpecLock> {
+        self.check_drift()?;
         self.execute(Self::inner_install)
     }
 
@@ -39,48 +98,306 @@ impl<'spec> Installer<'spec> {
         self.execute(Self::inner_update)
     }
 
+    /// Runs the callback for every dependency, importing the files into a
+    /// staging folder. Only when all the dependencies succeed, the staging
+    /// folder replaces the vendor folder, otherwise the vendor folder is left
+    /// untouched.
     fn execute<F>(mut self, callback: F) -> Result<SpecLock>
     where
-        F: (Fn(&Installer<'spec>, &Dependency) -> Result<LockedDependency>) + Sync + Send,
+        F: (Fn(&Installer<'spec>, &Dependency) -> Result<Imported>) + Sync + Send,
     {
+        let problems = self.spec.problems();
+        if !problems.is_empty() {
+            for problem in &problems {
+                log::error!("{problem}");
+            }
+            return Err(format_err!(
+                "{} problems found in the spec, see lint",
+                problems.len()
+            ));
+        }
+
+        let violations: Vec<_> = self
+            .spec
+            .deps
+            .iter()
+            .flat_map(|dep| self.spec.policy_violations(dep))
+            .collect();
+        check_violations(&violations)?;
+
         self.cache.initialize()?;
-        recreate_vendor_path(&self.spec.vendor)?;
+        recreate_vendor_path(&self.staging)?;
 
-        let updated_locks: Vec<_> = thread::scope(|s| {
-            self.spec
+        let results: Vec<_> = thread::scope(|s| {
+            let handles: Vec<_> = self
+                .spec
                 .deps
                 .iter()
-                .map(|dep| s.spawn(|| callback(&self, dep)))
-                .filter_map(|handle| handle.join().ok())
-                .flatten()
+                .map(|dep| (dep, s.spawn(|| callback(&self, dep))))
+                .collect();
+            handles
+                .into_iter()
+                .map(|(dep, handle)| {
+                    let result = handle
+                        .join()
+                        .unwrap_or_else(|_| Err(format_err!("unexpected panic")));
+                    (dep, result)
+                })
                 .collect()
         });
 
-        for lock in updated_locks {
-            self.spec_lock.add_locked_dependency(lock);
+        let mut imported = vec![];
+        let mut failures = 0;
+        for (dep, result) in results {
+            match result {
+                Ok(it) => imported.push((dep, it)),
+                Err(err) => {
+                    log::error!("{}: {err}", dep.name());
+                    failures += 1;
+                }
+            }
+        }
+        if failures > 0 {
+            delete_vendor_path(&self.staging)?;
+            return Err(format_err!(
+                "{failures} of {} dependencies failed, vendor folder left untouched",
+                self.spec.deps.len()
+            ));
+        }
+
+        if let Err(err) = self
+            .check_policies(&imported)
+            .and_then(|()| self.run_hooks(&imported))
+            .and_then(|()| manifest::write(&self.staging, &self.spec.provenance, &imported))
+            .and_then(|()| self.check_local_changes())
+        {
+            delete_vendor_path(&self.staging)?;
+            return Err(err);
+        }
+
+        atomic::replace_dir(&self.staging, &self.spec.vendor)
+            .map_err(|err| format_err!("cannot replace vendor folder: {err}"))?;
+
+        for (_, it) in imported {
+            self.spec_lock.add_locked_dependency(it.lock);
         }
 
         Ok(self.spec_lock)
     }
 
-    fn inner_install(&self, dependency: &Dependency) -> Result<LockedDependency> {
+    /// Produces a unified diff of the local modifications made to the files
+    /// of the dependency in the vendor folder, against the files at the
+    /// locked reference with its patches applied. The diff can be added as
+    /// one more dependency patch.
+    pub fn create_patch(&self, dependency: &Dependency) -> Result<String> {
+        let pristine = atomic::sibling(&self.spec.vendor, "pristine");
+        recreate_vendor_path(&pristine)?;
+        let result = self
+            .import_pristine(dependency, &pristine)
+            .and_then(|()| patch::diff(&pristine, Path::new(&self.spec.vendor)));
+        delete_vendor_path(&pristine)?;
+        result
+    }
+
+    /// Compares the vendor folder against the manifest of the previous
+    /// install, the files modified locally that the staged ones would
+    /// overwrite are handled according to [`LocalChanges`]. Files that end
+    /// up the same, eg. once the modification is kept as a patch, are not a
+    /// concern, neither are missing files nor vendor folders without a
+    /// manifest.
+    fn check_local_changes(&self) -> Result<()> {
+        let vendor = Path::new(&self.spec.vendor);
+        if !vendor.join(manifest::MANIFEST).exists() {
+            return Ok(());
+        }
+        let modified: Vec<_> = Manifest::load(vendor)?
+            .verify(vendor)
+            .into_iter()
+            .filter(|mismatch| {
+                !mismatch.missing
+                    && fs::read(vendor.join(&mismatch.path)).ok()
+                        != fs::read(self.staging.join(&mismatch.path)).ok()
+            })
+            .collect();
+        if modified.is_empty() {
+            return Ok(());
+        }
+
+        match &self.local_changes {
+            LocalChanges::Refuse => {
+                for mismatch in &modified {
+                    log::error!("{mismatch}");
+                }
+                Err(format_err!(
+                    "{} vendored files were modified locally, keep them with patch create, or use \
+                     --force to discard them or --backup to stash them",
+                    modified.len()
+                ))
+            }
+            LocalChanges::Discard => {
+                for mismatch in &modified {
+                    log::warn!("{mismatch}, discarding it");
+                }
+                Ok(())
+            }
+            LocalChanges::Backup(backup) => {
+                for mismatch in &modified {
+                    let to = backup.join(&mismatch.path);
+                    if let Some(parent) = to.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    fs::copy(vendor.join(&mismatch.path), &to)
+                        .map_err(|err| format_err!("cannot back up {}: {err}", mismatch.path))?;
+                    log::warn!("{mismatch}, backed up to {}", to.display());
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Compares the spec against the lock, a dependency drifted when it is
+    /// not locked, or when it was locked with different filters, preset or
+    /// vendor folder. Locks without fingerprint cannot be checked.
+    fn check_drift(&self) -> Result<()> {
+        let mut drifted = 0;
+        for dep in &self.spec.deps {
+            let reason = match self.spec_lock.get_locked_dependency(&dep.name()) {
+                None => "is not in the lock",
+                Some(lock) => match &lock.fingerprint {
+                    Some(fingerprint) if *fingerprint != self.spec.fingerprint(dep) => {
+                        "filters, preset or vendor folder changed since it was locked"
+                    }
+                    _ => continue,
+                },
+            };
+            drifted += 1;
+            if self.frozen {
+                log::error!("{}: {reason}", dep.name());
+            } else {
+                log::warn!("{}: {reason}, the lock will be updated", dep.name());
+            }
+        }
+        if self.frozen && drifted > 0 {
+            return Err(format_err!(
+                "{drifted} dependencies drifted from the lock, refusing to install with --frozen"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks the policies against the vendored files of each dependency,
+    /// before the hooks run.
+    fn check_policies(&self, imported: &[(&Dependency, Imported)]) -> Result<()> {
+        let mut violations = vec![];
+        for (dep, imported) in imported {
+            let files: Vec<_> = imported
+                .files
+                .iter()
+                .chain(&imported.licenses)
+                .cloned()
+                .collect();
+            let vendored = Vendored {
+                dependency: dep,
+                kind: imported.kind,
+                files: &files,
+            };
+            for policy in self.spec.policies() {
+                violations.extend(policy.check_vendored(&self.staging, &vendored));
+            }
+        }
+        check_violations(&violations)
+    }
+
+    /// Runs the hooks of every dependency, in the order of the spec, and
+    /// then the spec hooks. Hooks run sequentially, once all the
+    /// dependencies have been vendored, so their outcome is deterministic.
+    fn run_hooks(&self, imported: &[(&Dependency, Imported)]) -> Result<()> {
+        let vendor = fs::canonicalize(&self.staging)?;
+        let vendor = vendor.to_string_lossy();
+        for (dep, it) in imported {
+            for hook in &dep.hooks {
+                hooks::run(
+                    hook,
+                    &self.staging,
+                    &[
+                        ("VENDIFY_VENDOR", &vendor),
+                        ("VENDIFY_NAME", &dep.name()),
+                        ("VENDIFY_URL", &dep.url),
+                        ("VENDIFY_REFNAME", &dep.refname),
+                        ("VENDIFY_COMMIT", &it.lock.refname),
+                    ],
+                )?;
+            }
+        }
+        for hook in &self.spec.hooks {
+            hooks::run(hook, &self.staging, &[("VENDIFY_VENDOR", &vendor)])?;
+        }
+        Ok(())
+    }
+
+    fn inner_install(&self, dependency: &Dependency) -> Result<Imported> {
         let _repository_lock = self.cache.lock_repository(dependency)?;
         let repository = self.cache.get_repository(dependency)?;
-        let dependency_lock = self.spec_lock.get_locked_dependency(&dependency.url);
-        let importer = Importer::new(self.spec, dependency, dependency_lock, &repository);
+        let dependency_lock = self.spec_lock.get_locked_dependency(&dependency.name());
+        let importer = Importer::new(
+            self.spec,
+            dependency,
+            dependency_lock,
+            &repository,
+            &self.staging,
+            &self.config,
+        );
 
         importer.install()
     }
 
-    fn inner_update(&self, dependency: &Dependency) -> Result<LockedDependency> {
+    fn import_pristine(&self, dependency: &Dependency, to: &Path) -> Result<()> {
+        self.cache.initialize()?;
         let _repository_lock = self.cache.lock_repository(dependency)?;
         let repository = self.cache.get_repository(dependency)?;
-        let importer = Importer::new(self.spec, dependency, None, &repository);
+        let dependency_lock = self.spec_lock.get_locked_dependency(&dependency.name());
+        let importer = Importer::new(
+            self.spec,
+            dependency,
+            dependency_lock,
+            &repository,
+            to,
+            &self.config,
+        );
+
+        importer.pristine()
+    }
+
+    fn inner_update(&self, dependency: &Dependency) -> Result<Imported> {
+        let _repository_lock = self.cache.lock_repository(dependency)?;
+        let repository = self.cache.get_repository(dependency)?;
+        let importer = Importer::new(
+            self.spec,
+            dependency,
+            None,
+            &repository,
+            &self.staging,
+            &self.config,
+        );
 
         importer.update()
     }
 }
 
+/// Logs every violation, failing when there is any.
+fn check_violations(violations: &[Violation]) -> Result<()> {
+    if violations.is_empty() {
+        return Ok(());
+    }
+    for violation in violations {
+        log::error!("{violation}");
+    }
+    Err(format_err!(
+        "{} policy violations found, see above",
+        violations.len()
+    ))
+}
+
 fn recreate_vendor_path<P: AsRef<Path>>(path: P) -> Result<()> {
     delete_vendor_path(&path)?;
     create_vendor_path(&path)
@@ -118,9 +435,567 @@ fn create_vendor_path<P: AsRef<Path>>(path: P) -> Result<()> {
 #[cfg(test)]
 mod tests {
 
+    use sha2::Digest;
+    use sha2::Sha256;
+
     use super::*;
+    use crate::deps::Symlinks;
+    use crate::filters::FilterKind;
+    use crate::filters::Filters;
+    use crate::installer::manifest::Manifest;
+    use crate::policy::Policy;
+    use crate::policy::RefnameKind;
+    use crate::provenance::Provenance;
+    use crate::signatures::SignaturePolicy;
+    use crate::svec;
+    use crate::test_utils::commit_file;
+    use crate::test_utils::commit_signed_file;
+    use crate::test_utils::commit_symlink;
+    use crate::test_utils::init_upstream;
+    use crate::test_utils::read_to_string;
+    use crate::test_utils::ssh_key;
+    use crate::test_utils::tag_head;
+    use crate::test_utils::tag_signed_head;
     use crate::test_utils::tempdir;
     use crate::test_utils::write_to;
+    use crate::test_utils::TestContext;
+    use crate::transforms::Transform;
+
+    fn build_spec(ctx: &TestContext, urls: &[String]) -> Spec {
+        let mut spec = Spec::with_preset(&ctx.preset);
+        spec.filters = Filters::new();
+        for url in urls {
+            let mut dep = Dependency::new(url, "master");
+            dep.filters.add(FilterKind::Target(svec!["file.txt"]));
+            spec.deps.push(dep);
+        }
+        spec
+    }
+
+    #[test]
+    fn test_installer_install_replaces_vendor() -> Result<()> {
+        let ctx = &TestContext::new();
+        let root = tempdir();
+        let upstream = root.path().join("upstream");
+        commit_file(&init_upstream(&upstream), "file.txt", "data");
+        let spec = &build_spec(ctx, &[upstream.to_string_lossy().to_string()]);
+        fs::create_dir_all(&spec.vendor)?;
+        write_to(Path::new(&spec.vendor).join("stale.txt"), "");
+
+        let installer = Installer::new(Cache::new(&ctx.preset), spec, SpecLock::new());
+        let spec_lock = installer.install()?;
+
+        let vendor = Path::new(&spec.vendor);
+        assert_eq!(1, spec_lock.deps.len());
+        assert!(vendor.join("file.txt").exists());
+        assert!(!vendor.join("stale.txt").exists());
+        assert!(!atomic::sibling(vendor, "tmp").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_copies_licenses_and_writes_manifest() -> Result<()> {
+        let ctx = &TestContext::new();
+        let root = tempdir();
+        let upstream = root.path().join("upstream");
+        let repo = init_upstream(&upstream);
+        commit_file(&repo, "file.txt", "data");
+        commit_file(
+            &repo,
+            "LICENSE",
+            "Permission is hereby granted, free of charge, to any person",
+        );
+        let spec = &build_spec(ctx, &[upstream.to_string_lossy().to_string()]);
+
+        let installer = Installer::new(Cache::new(&ctx.preset), spec, SpecLock::new());
+        let spec_lock = installer.install()?;
+
+        let vendor = Path::new(&spec.vendor);
+        assert!(!vendor.join("LICENSE").exists(), "not targeted");
+        assert!(vendor.join("LICENSES/upstream/LICENSE").exists());
+        let manifest: serde_yaml::Value =
+            serde_yaml::from_str(&read_to_string(&vendor.join(manifest::MANIFEST)))?;
+        let entry = &manifest["dependencies"][0];
+        assert_eq!("upstream", entry["name"].as_str().unwrap());
+        assert_eq!(spec_lock.deps[0].refname, entry["commit"].as_str().unwrap());
+        assert_eq!("MIT", entry["license"].as_str().unwrap());
+        let paths: Vec<_> = entry["files"]
+            .as_sequence()
+            .unwrap()
+            .iter()
+            .map(|file| file["path"].as_str().unwrap())
+            .collect();
+        assert_eq!(vec!["file.txt", "LICENSES/upstream/LICENSE"], paths);
+        assert_eq!(
+            "3a6eb0790f39ac87c94f3856b2dd2c5d110e6811602261a9a923d3bb23adc8b7",
+            entry["files"][0]["sha256"].as_str().unwrap()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_manifest_lists_preserved_symlinks() -> Result<()> {
+        let ctx = &TestContext::new();
+        let root = tempdir();
+        let upstream = root.path().join("upstream");
+        let repo = init_upstream(&upstream);
+        commit_file(&repo, "file.txt", "data");
+        commit_symlink(&repo, "dangling.txt", "missing.txt");
+        let spec = &mut build_spec(ctx, &[upstream.to_string_lossy().to_string()]);
+        spec.deps[0].symlinks = Symlinks::Preserve;
+        spec.deps[0]
+            .filters
+            .add(FilterKind::Target(svec!["dangling.txt"]));
+
+        Installer::new(Cache::new(&ctx.preset), spec, SpecLock::new()).install()?;
+
+        let vendor = Path::new(&spec.vendor);
+        let manifest = Manifest::load(vendor)?;
+        let files = &manifest.dependencies[0].files;
+        assert_eq!(
+            vec!["dangling.txt", "file.txt"],
+            files
+                .iter()
+                .map(|file| file.path.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            format!("{:x}", Sha256::digest(b"missing.txt")),
+            files[0].sha256,
+            "hash of the link target"
+        );
+        assert!(manifest.verify(vendor).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_provenance_headers_and_verify() -> Result<()> {
+        let ctx = &TestContext::new();
+        let root = tempdir();
+        let upstream = root.path().join("upstream");
+        commit_file(&init_upstream(&upstream), "file.txt", "data");
+        let url = &upstream.to_string_lossy().to_string();
+        let spec = &mut build_spec(ctx, std::slice::from_ref(url));
+        spec.provenance.push(Provenance {
+            template: "// vendored from {url}@{commit}".into(),
+            extensions: svec!["txt"],
+        });
+
+        let installer = Installer::new(Cache::new(&ctx.preset), spec, SpecLock::new());
+        let commit = installer.install()?.deps[0].refname.clone();
+
+        let vendor = Path::new(&spec.vendor);
+        assert_eq!(
+            format!("// vendored from {url}@{commit}\ndata"),
+            read_to_string(&vendor.join("file.txt"))
+        );
+        let manifest = Manifest::load(vendor)?;
+        assert_eq!(
+            "3a6eb0790f39ac87c94f3856b2dd2c5d110e6811602261a9a923d3bb23adc8b7",
+            manifest.dependencies[0].files[0].sha256,
+            "hash of the contents without the header"
+        );
+        assert!(manifest.verify(vendor).is_empty());
+
+        write_to(vendor.join("file.txt"), "data");
+        assert!(manifest.verify(vendor).is_empty());
+        write_to(vendor.join("file.txt"), "modified");
+        assert_eq!(
+            vec!["upstream: file.txt was modified"],
+            manifest
+                .verify(vendor)
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_provenance_template_change_is_not_a_local_change() -> Result<()> {
+        let ctx = &TestContext::new();
+        let root = tempdir();
+        let upstream = root.path().join("upstream");
+        commit_file(&init_upstream(&upstream), "file.txt", "data");
+        let spec = &mut build_spec(ctx, &[upstream.to_string_lossy().to_string()]);
+        spec.provenance.push(Provenance {
+            template: "// vendored from {url}".into(),
+            extensions: svec!["txt"],
+        });
+        Installer::new(Cache::new(&ctx.preset), spec, SpecLock::new()).install()?;
+
+        spec.provenance[0].template = "// vendored {name}".into();
+        Installer::new(Cache::new(&ctx.preset), spec, SpecLock::new()).install()?;
+
+        let vendor = Path::new(&spec.vendor);
+        assert_eq!(
+            "// vendored upstream\ndata",
+            read_to_string(&vendor.join("file.txt"))
+        );
+        assert!(Manifest::load(vendor)?.verify(vendor).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_applies_transforms_and_hooks() -> Result<()> {
+        let ctx = &TestContext::new();
+        let root = tempdir();
+        let upstream = root.path().join("upstream");
+        commit_file(&init_upstream(&upstream), "file.txt", "data");
+        let spec = &mut build_spec(ctx, &[upstream.to_string_lossy().to_string()]);
+        let install =
+            |spec: &Spec| Installer::new(Cache::new(&ctx.preset), spec, SpecLock::new()).install();
+        let plain_hash = install(spec)?.deps[0].hash.clone();
+
+        spec.deps[0].transforms.push(Transform::Header {
+            text: "# DO NOT EDIT".into(),
+            extensions: vec![],
+        });
+        spec.deps[0]
+            .hooks
+            .push("echo \"$VENDIFY_URL\" > dep-hook.txt".into());
+        spec.hooks.push("ls > spec-hook.txt".into());
+        let spec_lock = install(spec)?;
+
+        let vendor = Path::new(&spec.vendor);
+        assert!(plain_hash.is_some());
+        assert_ne!(plain_hash, spec_lock.deps[0].hash, "hash covers transforms");
+        assert_eq!(
+            "# DO NOT EDIT\ndata",
+            read_to_string(&vendor.join("file.txt"))
+        );
+        assert_eq!(
+            format!("{}\n", upstream.display()),
+            read_to_string(&vendor.join("dep-hook.txt"))
+        );
+        assert!(read_to_string(&vendor.join("spec-hook.txt")).contains("dep-hook.txt"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_create_and_apply_patch() -> Result<()> {
+        let ctx = &TestContext::new();
+        let root = tempdir();
+        let upstream = root.path().join("upstream");
+        commit_file(&init_upstream(&upstream), "file.txt", "data\n");
+        let spec = &mut build_spec(ctx, &[upstream.to_string_lossy().to_string()]);
+        let spec_lock = Installer::new(Cache::new(&ctx.preset), spec, SpecLock::new()).install()?;
+        let vendor = Path::new(&spec.vendor);
+        write_to(vendor.join("file.txt"), "patched\n");
+
+        let installer = Installer::new(Cache::new(&ctx.preset), spec, spec_lock);
+        let diff = installer.create_patch(&spec.deps[0])?;
+        let patch_path = root.path().join("fix.patch");
+        write_to(&patch_path, &diff);
+        spec.deps[0]
+            .patches
+            .push(patch_path.to_string_lossy().to_string());
+        let spec_lock = Installer::new(Cache::new(&ctx.preset), spec, SpecLock::new()).install()?;
+
+        assert!(diff.contains("-data\n+patched\n"), "{diff}");
+        assert!(!atomic::sibling(vendor, "pristine").exists());
+        assert_eq!("patched\n", read_to_string(&vendor.join("file.txt")));
+
+        write_to(vendor.join("file.txt"), "patched twice\n");
+        let installer = Installer::new(Cache::new(&ctx.preset), spec, spec_lock.clone());
+        let second = installer.create_patch(&spec.deps[0])?;
+        assert!(
+            second.contains("-patched\n+patched twice\n"),
+            "only the new changes: {second}"
+        );
+
+        write_to(&patch_path, &diff.replace("-data", "-other"));
+        let result = Installer::new(Cache::new(&ctx.preset), spec, spec_lock).install();
+        assert!(result.is_err(), "patch should not apply");
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_local_changes() -> Result<()> {
+        let ctx = &TestContext::new();
+        let root = tempdir();
+        let upstream = root.path().join("upstream");
+        commit_file(&init_upstream(&upstream), "file.txt", "data");
+        let spec = &build_spec(ctx, &[upstream.to_string_lossy().to_string()]);
+        let install = |local_changes: LocalChanges| {
+            Installer::new(Cache::new(&ctx.preset), spec, SpecLock::new())
+                .with_local_changes(local_changes)
+                .install()
+        };
+        install(LocalChanges::Refuse)?;
+        let vendor = Path::new(&spec.vendor);
+        write_to(vendor.join("file.txt"), "edited");
+
+        let result = install(LocalChanges::Refuse);
+        assert_eq!(
+            "1 vendored files were modified locally, keep them with patch create, or use --force \
+             to discard them or --backup to stash them",
+            result.unwrap_err().to_string()
+        );
+        assert_eq!("edited", read_to_string(&vendor.join("file.txt")));
+
+        let backup = root.path().join("backup");
+        install(LocalChanges::Backup(backup.clone()))?;
+        assert_eq!("edited", read_to_string(&backup.join("file.txt")));
+        assert_eq!("data", read_to_string(&vendor.join("file.txt")));
+
+        write_to(vendor.join("file.txt"), "edited");
+        install(LocalChanges::Discard)?;
+        assert_eq!("data", read_to_string(&vendor.join("file.txt")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_verifies_signatures() -> Result<()> {
+        let ctx = &TestContext::new();
+        let root = tempdir();
+        let (key, allowed_signers) = ssh_key(&root.path().join("keys"), "alice");
+        let upstream = root.path().join("upstream");
+        let repo = init_upstream(&upstream);
+        commit_file(&repo, "file.txt", "data");
+        let spec = &mut build_spec(ctx, &[upstream.to_string_lossy().to_string()]);
+        spec.deps[0].verify_signatures = Some(SignaturePolicy {
+            ssh_allowed_signers: Some(allowed_signers.to_string_lossy().to_string()),
+            ..SignaturePolicy::default()
+        });
+        let install =
+            |spec: &Spec| Installer::new(Cache::new(&ctx.preset), spec, SpecLock::new()).update();
+
+        let err = install(spec).unwrap_err();
+        assert_eq!(
+            "1 of 1 dependencies failed, vendor folder left untouched",
+            err.to_string()
+        );
+
+        commit_signed_file(&repo, "file.txt", "signed", &key);
+        let spec_lock = install(spec)?;
+        let signer = spec_lock.deps[0].signer.as_deref().unwrap();
+        assert!(signer.starts_with("SHA256:"), "{signer}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_verifies_the_locked_commit_not_the_tag() -> Result<()> {
+        let ctx = &TestContext::new();
+        let root = tempdir();
+        let (key, allowed_signers) = ssh_key(&root.path().join("keys"), "alice");
+        let upstream = root.path().join("upstream");
+        let repo = init_upstream(&upstream);
+        commit_file(&repo, "file.txt", "1.0.0");
+        tag_signed_head(&repo, "v1.0.0", &key);
+        let unsigned = commit_file(&repo, "file.txt", "unsigned");
+        let spec = &mut build_spec(ctx, &[upstream.to_string_lossy().to_string()]);
+        spec.deps[0].refname = "^1.0".into();
+        spec.deps[0].verify_signatures = Some(SignaturePolicy {
+            ssh_allowed_signers: Some(allowed_signers.to_string_lossy().to_string()),
+            ..SignaturePolicy::default()
+        });
+
+        let mut spec_lock =
+            Installer::new(Cache::new(&ctx.preset), spec, SpecLock::new()).update()?;
+        assert!(spec_lock.deps[0].signer.is_some());
+
+        spec_lock.deps[0].refname = unsigned.to_string();
+        let result = Installer::new(Cache::new(&ctx.preset), spec, spec_lock).install();
+
+        assert!(
+            result.is_err(),
+            "the signed tag does not vouch for {unsigned}"
+        );
+        assert_eq!(
+            "1.0.0",
+            read_to_string(&Path::new(&spec.vendor).join("file.txt"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_detects_drift() -> Result<()> {
+        let ctx = &TestContext::new();
+        let root = tempdir();
+        let upstream = root.path().join("upstream");
+        let repo = init_upstream(&upstream);
+        commit_file(&repo, "file.txt", "data");
+        commit_file(&repo, "other.txt", "data");
+        let spec = &mut build_spec(ctx, &[upstream.to_string_lossy().to_string()]);
+        let install = |spec: &Spec, spec_lock: SpecLock, frozen: bool| {
+            Installer::new(Cache::new(&ctx.preset), spec, spec_lock)
+                .with_frozen(frozen)
+                .install()
+        };
+        assert!(install(spec, SpecLock::new(), true).is_err(), "not locked");
+        let spec_lock = install(spec, SpecLock::new(), false)?;
+        let fingerprint = spec_lock.deps[0].fingerprint.clone();
+        let spec_lock = install(spec, spec_lock, true)?;
+
+        spec.deps[0]
+            .filters
+            .add(FilterKind::Target(svec!["other.txt"]));
+        let result = install(spec, spec_lock.clone(), true);
+        let spec_lock = install(spec, spec_lock, false)?;
+
+        assert_eq!(
+            Some(spec.fingerprint(&spec.deps[0])),
+            spec_lock.deps[0].fingerprint
+        );
+        assert_ne!(fingerprint, spec_lock.deps[0].fingerprint);
+        assert!(result.is_err(), "frozen install refuses drift");
+        assert!(Path::new(&spec.vendor).join("other.txt").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_resolves_semver_range() -> Result<()> {
+        let ctx = &TestContext::new();
+        let root = tempdir();
+        let upstream = root.path().join("upstream");
+        let repo = init_upstream(&upstream);
+        for version in ["1.3.0", "1.4.0", "1.5.0", "2.0.0"] {
+            commit_file(&repo, "file.txt", version);
+            tag_head(&repo, &format!("v{version}"));
+        }
+        let spec = &mut build_spec(ctx, &[upstream.to_string_lossy().to_string()]);
+        spec.deps[0].refname = "^1.4".into();
+        let spec_lock = Installer::new(Cache::new(&ctx.preset), spec, SpecLock::new()).update()?;
+        let locked = spec_lock.deps[0].clone();
+
+        commit_file(&repo, "file.txt", "1.6.0");
+        tag_head(&repo, "v1.6.0");
+        let spec_lock = Installer::new(Cache::new(&ctx.preset), spec, spec_lock).install()?;
+
+        let vendor = Path::new(&spec.vendor);
+        assert_eq!(Some("v1.5.0".into()), locked.version);
+        assert_eq!(locked, spec_lock.deps[0], "install keeps the lock");
+        assert_eq!("1.5.0", read_to_string(&vendor.join("file.txt")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_same_url_under_different_names() -> Result<()> {
+        let ctx = &TestContext::new();
+        let root = tempdir();
+        let upstream = root.path().join("upstream");
+        let repo = init_upstream(&upstream);
+        let old = commit_file(&repo, "one.txt", "old");
+        repo.branch("old", &repo.find_commit(old)?, false)?;
+        commit_file(&repo, "one.txt", "new");
+        commit_file(&repo, "two.txt", "new");
+        let url = upstream.to_string_lossy().to_string();
+        let spec = &mut build_spec(ctx, &[url.clone(), url]);
+        for (dep, (name, refname, target)) in spec
+            .deps
+            .iter_mut()
+            .zip([("one", "old", "one.txt"), ("two", "master", "two.txt")])
+        {
+            dep.name = Some(name.into());
+            dep.refname = refname.into();
+            dep.filters = Filters::new();
+            dep.filters.add(FilterKind::Target(svec![target]));
+        }
+
+        let spec_lock = Installer::new(Cache::new(&ctx.preset), spec, SpecLock::new()).install()?;
+
+        let vendor = Path::new(&spec.vendor);
+        assert_eq!(2, spec_lock.deps.len());
+        assert!(spec_lock.get_locked_dependency("one").is_some());
+        assert!(spec_lock.get_locked_dependency("two").is_some());
+        assert_eq!("old", read_to_string(&vendor.join("one.txt")));
+        assert_eq!("new", read_to_string(&vendor.join("two.txt")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_refuses_spec_with_problems() -> Result<()> {
+        let ctx = &TestContext::new();
+        let spec = &mut build_spec(ctx, &svec!["some-url"]);
+        spec.deps[0]
+            .filters
+            .add(FilterKind::Target(svec!["../escape"]));
+
+        let result = Installer::new(Cache::new(&ctx.preset), spec, SpecLock::new()).install();
+
+        assert!(result.is_err(), "install should fail");
+        assert!(!atomic::sibling(&spec.vendor, "tmp").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_enforces_policy() -> Result<()> {
+        let ctx = &TestContext::new();
+        let root = tempdir();
+        let upstream = root.path().join("upstream");
+        commit_file(&init_upstream(&upstream), "file.txt", "0123456789");
+        let spec = &mut build_spec(ctx, &[upstream.to_string_lossy().to_string()]);
+
+        spec.policy = Policy {
+            max_size: Some(10),
+            ..Policy::default()
+        };
+        Installer::new(Cache::new(&ctx.preset), spec, SpecLock::new()).install()?;
+
+        spec.policy = Policy {
+            forbidden_refnames: vec![RefnameKind::Branch],
+            max_size: Some(9),
+            ..Policy::default()
+        };
+        let result = Installer::new(Cache::new(&ctx.preset), spec, SpecLock::new()).install();
+
+        assert_eq!(
+            "2 policy violations found, see above",
+            result.unwrap_err().to_string()
+        );
+        assert!(Path::new(&spec.vendor).join("file.txt").exists());
+        assert!(!atomic::sibling(&spec.vendor, "tmp").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_hexadecimal_branch_is_not_a_commit() -> Result<()> {
+        let ctx = &TestContext::new();
+        let root = tempdir();
+        let upstream = root.path().join("upstream");
+        let repo = init_upstream(&upstream);
+        let head = repo.find_commit(commit_file(&repo, "file.txt", "data"))?;
+        repo.branch("cafe123", &head, false)?;
+        let spec = &mut build_spec(ctx, &[upstream.to_string_lossy().to_string()]);
+        spec.deps[0].refname = "cafe123".into();
+
+        spec.policy.forbidden_refnames = vec![RefnameKind::Commit];
+        Installer::new(Cache::new(&ctx.preset), spec, SpecLock::new()).install()?;
+
+        spec.policy.forbidden_refnames = vec![RefnameKind::Branch];
+        let result = Installer::new(Cache::new(&ctx.preset), spec, SpecLock::new()).install();
+        assert!(result.is_err(), "cafe123 is a branch");
+        Ok(())
+    }
+
+    #[test]
+    fn test_installer_failure_leaves_vendor_untouched() -> Result<()> {
+        let ctx = &TestContext::new();
+        let root = tempdir();
+        let upstream = root.path().join("upstream");
+        commit_file(&init_upstream(&upstream), "file.txt", "data");
+        let missing = root.path().join("missing");
+        let spec = &build_spec(
+            ctx,
+            &[
+                upstream.to_string_lossy().to_string(),
+                missing.to_string_lossy().to_string(),
+            ],
+        );
+        fs::create_dir_all(&spec.vendor)?;
+        write_to(Path::new(&spec.vendor).join("previous.txt"), "");
+
+        let installer = Installer::new(Cache::new(&ctx.preset), spec, SpecLock::new());
+        let result = installer.install();
+
+        let vendor = Path::new(&spec.vendor);
+        assert!(result.is_err(), "install should fail");
+        assert!(vendor.join("previous.txt").exists());
+        assert!(!vendor.join("file.txt").exists());
+        assert!(!atomic::sibling(vendor, "tmp").exists());
+        Ok(())
+    }
 
     #[test]
     fn test_ensure_vendor_empty_root() {
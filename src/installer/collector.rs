@@ -1,15 +1,26 @@
+use std::collections::HashSet;
 use std::fs;
+use std::os::unix;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Component;
 use std::path::Path;
 use std::path::PathBuf;
 
+use anyhow::format_err;
 use anyhow::Result;
+use sha2::Digest;
+use sha2::Sha256;
 use walkdir::DirEntry;
 
+use super::lfs;
 use super::selector::Selector;
+use crate::deps::Symlinks;
+use crate::transforms::Transformer;
 
 /// Returns an iterator of [`CollectedPath`].
 pub struct Collector {
     selector: Selector,
+    symlinks: Symlinks,
 }
 
 /// Represents a file that has been collected, it allows to copy the file
@@ -20,27 +31,86 @@ pub struct Collector {
 pub struct CollectedPath {
     pub src: PathBuf,
     pub src_rel: PathBuf,
+
+    /// Target of the symbolic link, when the path is a link to preserve.
+    pub link: Option<PathBuf>,
+}
+
+/// Controls how the contents of a [`CollectedPath`] are produced when
+/// copying it.
+#[derive(Default)]
+pub struct CopyOptions {
+    /// Local Git LFS object store, see [`lfs::resolve`].
+    pub lfs_store: Option<PathBuf>,
+
+    pub transformer: Transformer,
+
+    /// Paths to vendor as executable, relative to the dependency root. When
+    /// unset, every file is vendored with 0644.
+    pub executables: Option<HashSet<PathBuf>>,
+}
+
+impl CopyOptions {
+    /// Returns the permissions the file at `rel` is vendored with.
+    pub fn mode(&self, rel: &Path) -> u32 {
+        match &self.executables {
+            Some(executables) if executables.contains(rel) => 0o755,
+            _ => 0o644,
+        }
+    }
 }
 
 impl Collector {
     pub fn from(selector: Selector) -> Self {
-        Self { selector }
+        Self {
+            selector,
+            symlinks: Symlinks::default(),
+        }
+    }
+
+    /// Sets how the symbolic links are collected.
+    #[must_use]
+    pub fn with_symlinks(mut self, symlinks: Symlinks) -> Self {
+        self.symlinks = symlinks;
+        self
     }
 
     pub fn collect<P: AsRef<Path>>(&self, from: &P) -> impl Iterator<Item = CollectedPath> + '_ {
         let from = from.as_ref().to_owned();
+        let from_filter = from.clone();
         let from_copy = from.clone();
         walkdir::WalkDir::new(&from)
+            .follow_links(self.symlinks == Symlinks::Follow)
+            .sort_by_file_name()
             .into_iter()
             .filter_entry(move |entry| self.select_entry(&from, entry))
             .filter_map(Result::ok)
-            .filter(|entry| entry.path().is_file())
+            .filter(move |entry| self.keep_entry(&from_filter, entry))
             .map(move |entry| CollectedPath::new(&from_copy, &entry))
     }
 
+    /// Keeps files, and the symbolic links when they are preserved. When
+    /// links are followed, walkdir yields their targets instead.
+    fn keep_entry<P: AsRef<Path>>(&self, from: &P, entry: &DirEntry) -> bool {
+        let file_type = entry.file_type();
+        if !file_type.is_symlink() {
+            return file_type.is_file();
+        }
+        if self.symlinks == Symlinks::Preserve {
+            return true;
+        }
+        log::warn!(
+            "\tskipping symlink {}, see the symlinks setting",
+            relative(from, entry).display()
+        );
+        false
+    }
+
+    /// Preserved links to directories are not traversed, they are selected
+    /// like files, so they have to match the filters themselves.
     fn select_entry<P: AsRef<Path>>(&self, from: &P, entry: &DirEntry) -> bool {
         let rel = relative(from, entry);
-        if entry.path().is_dir() {
+        if entry.file_type().is_dir() {
             self.selector.select_dir(&rel)
         } else {
             self.selector.select_file(&rel)
@@ -56,22 +126,108 @@ impl From<Selector> for Collector {
 
 impl CollectedPath {
     pub fn new<P: AsRef<Path>>(from: &P, entry: &DirEntry) -> CollectedPath {
+        let link = if entry.file_type().is_symlink() {
+            fs::read_link(entry.path()).ok()
+        } else {
+            None
+        };
         Self {
             src: entry.path().to_owned(),
             src_rel: relative(from, entry),
+            link,
         }
     }
 
     /// Copies the collected file and its contents from the source to the
-    /// destination path
-    pub fn copy<P: AsRef<Path>>(&self, to: &P) -> Result<()> {
-        let to = to.as_ref().join(&self.src_rel);
-        if let Some(parent) = to.parent() {
-            fs::create_dir_all(parent)?;
-        };
-        fs::copy(&self.src, &to)?;
-        Ok(())
+    /// destination path, resolving Git LFS pointers and applying the
+    /// transforms on the way.
+    ///
+    /// Returns the SHA-256 of the contents that have been written. Files get
+    /// the permissions of [`CopyOptions::mode`], regardless of the ones in
+    /// the source.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the source resolves outside
+    /// of the dependency, or the destination outside of `to`.
+    pub fn copy<P: AsRef<Path>>(&self, to: &P, opts: &CopyOptions) -> Result<String> {
+        self.inner_copy(to.as_ref(), opts)
+            .map_err(|err| format_err!("{}: {err}", self.src_rel.display()))
+    }
+
+    fn inner_copy(&self, root: &Path, opts: &CopyOptions) -> Result<String> {
+        let to = self.destination(root)?;
+        if let Some(target) = &self.link {
+            if !resolves_inside(&self.src_rel, target) {
+                return Err(format_err!(
+                    "symlink to {} points outside of the dependency",
+                    target.display()
+                ));
+            }
+            unix::fs::symlink(target, &to)?;
+            let digest = Sha256::digest(target.to_string_lossy().as_bytes());
+            return Ok(format!("{digest:x}"));
+        }
+
+        let dependency_root = self
+            .src
+            .ancestors()
+            .nth(self.src_rel.components().count())
+            .unwrap_or(&self.src);
+        if !fs::canonicalize(&self.src)?.starts_with(fs::canonicalize(dependency_root)?) {
+            return Err(format_err!("resolves outside of the dependency"));
+        }
+
+        let contents = lfs::resolve(fs::read(&self.src)?, opts.lfs_store.as_deref())?;
+        let contents = opts.transformer.apply(&self.src_rel, contents);
+        fs::write(&to, &contents)?;
+        fs::set_permissions(&to, fs::Permissions::from_mode(opts.mode(&self.src_rel)))?;
+        Ok(format!("{:x}", Sha256::digest(&contents)))
     }
+
+    /// Returns the destination path inside `root`, creating its parent
+    /// directories. Fails when the destination would resolve outside of
+    /// `root`, eg. through a symbolic link, which is never written through.
+    fn destination(&self, root: &Path) -> Result<PathBuf> {
+        if !self
+            .src_rel
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(format_err!("invalid path"));
+        }
+
+        let to = root.join(&self.src_rel);
+        let parent = to.parent().unwrap_or(root);
+        fs::create_dir_all(parent)?;
+        if !fs::canonicalize(parent)?.starts_with(fs::canonicalize(root)?) {
+            return Err(format_err!(
+                "destination resolves outside of the vendor folder"
+            ));
+        }
+        if to
+            .symlink_metadata()
+            .is_ok_and(|meta| meta.file_type().is_symlink())
+        {
+            fs::remove_file(&to)?;
+        }
+        Ok(to)
+    }
+}
+
+/// Whether the relative symbolic link `target`, found at `path`, resolves
+/// to a path inside the root `path` is relative to.
+fn resolves_inside(path: &Path, target: &Path) -> bool {
+    let mut depth = path.components().count().saturating_sub(1);
+    for component in target.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir if depth > 0 => depth -= 1,
+            _ => return false,
+        }
+    }
+    true
 }
 
 fn relative<P: AsRef<Path>>(from: &P, entry: &DirEntry) -> PathBuf {
@@ -86,10 +242,188 @@ fn relative<P: AsRef<Path>>(from: &P, entry: &DirEntry) -> PathBuf {
 mod tests {
 
     use super::*;
+    use crate::filters::FilterKind;
+    use crate::filters::Filters;
+    use crate::svec;
     use crate::test_utils::read_to_string;
     use crate::test_utils::tempdir;
     use crate::test_utils::write_to;
 
+    /// Builds a dependency with a regular file, and links to it, to a
+    /// directory and to a path outside of the dependency.
+    fn build_dependency(root: &Path) -> Result<PathBuf> {
+        let dep = root.join("dep");
+        fs::create_dir_all(dep.join("dir"))?;
+        write_to(dep.join("dir/file.txt"), "data");
+        unix::fs::symlink("dir/file.txt", dep.join("inside.txt"))?;
+        unix::fs::symlink("dir", dep.join("linked-dir"))?;
+        write_to(root.join("secret.txt"), "secret");
+        unix::fs::symlink("../secret.txt", dep.join("outside.txt"))?;
+        Ok(dep)
+    }
+
+    fn copy_all(symlinks: Symlinks, dep: &Path, to: &Path) -> Vec<Result<String>> {
+        let mut filters = Filters::new();
+        filters.add(FilterKind::Extension(svec!["txt"]));
+        Collector::from(Selector::from(filters))
+            .with_symlinks(symlinks)
+            .collect(&dep)
+            .map(|collected| collected.copy(&to, &CopyOptions::default()))
+            .collect()
+    }
+
+    #[test]
+    fn test_collected_path_copy_sets_modes() -> Result<()> {
+        let root = tempdir();
+        let dep = root.path().join("dep");
+        fs::create_dir_all(&dep)?;
+        write_to(dep.join("run.txt"), "#!/bin/sh");
+        write_to(dep.join("data.txt"), "data");
+        fs::set_permissions(dep.join("data.txt"), fs::Permissions::from_mode(0o777))?;
+        let to = root.path().join("vendor");
+        let mut filters = Filters::new();
+        filters.add(FilterKind::Extension(svec!["txt"]));
+        let collector = Collector::from(Selector::from(filters));
+        let mode =
+            |path: &str| fs::metadata(to.join(path)).map(|meta| meta.permissions().mode() & 0o777);
+
+        let opts = CopyOptions {
+            executables: Some(HashSet::from([PathBuf::from("run.txt")])),
+            ..CopyOptions::default()
+        };
+        for collected in collector.collect(&dep) {
+            collected.copy(&to, &opts)?;
+        }
+        assert_eq!(0o755, mode("run.txt")?);
+        assert_eq!(0o644, mode("data.txt")?);
+
+        for collected in collector.collect(&dep) {
+            collected.copy(&to, &CopyOptions::default())?;
+        }
+        assert_eq!(0o644, mode("run.txt")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_collector_skips_symlinks() -> Result<()> {
+        let root = tempdir();
+        let dep = build_dependency(root.path())?;
+        let to = root.path().join("vendor");
+
+        let results = copy_all(Symlinks::Skip, &dep, &to);
+
+        assert_eq!(1, results.len());
+        assert!(to.join("dir/file.txt").exists());
+        assert!(!to.join("inside.txt").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_collector_preserves_symlinks_inside_dependency() -> Result<()> {
+        let root = tempdir();
+        let dep = build_dependency(root.path())?;
+        let to = root.path().join("vendor");
+
+        let results = copy_all(Symlinks::Preserve, &dep, &to);
+
+        let failed: Vec<_> = results.iter().filter_map(|r| r.as_ref().err()).collect();
+        assert_eq!(1, failed.len());
+        assert!(
+            failed[0].to_string().starts_with("outside.txt: "),
+            "{}",
+            failed[0]
+        );
+        assert_eq!(
+            PathBuf::from("dir/file.txt"),
+            fs::read_link(to.join("inside.txt"))?
+        );
+        assert!(
+            fs::symlink_metadata(to.join("linked-dir")).is_err(),
+            "link to a directory without txt extension"
+        );
+        assert_eq!("data", read_to_string(&to.join("inside.txt")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_collector_preserves_targeted_directory_symlinks() -> Result<()> {
+        let root = tempdir();
+        let dep = build_dependency(root.path())?;
+        let to = root.path().join("vendor");
+        let mut filters = Filters::new();
+        filters.add(FilterKind::Target(svec!["linked-dir"]));
+
+        for collected in Collector::from(Selector::from(filters))
+            .with_symlinks(Symlinks::Preserve)
+            .collect(&dep)
+        {
+            collected.copy(&to, &CopyOptions::default())?;
+        }
+
+        assert_eq!(PathBuf::from("dir"), fs::read_link(to.join("linked-dir"))?);
+        assert!(!to.join("dir").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_collector_follows_symlinks_inside_dependency() -> Result<()> {
+        let root = tempdir();
+        let dep = build_dependency(root.path())?;
+        let to = root.path().join("vendor");
+
+        let results = copy_all(Symlinks::Follow, &dep, &to);
+
+        let failed: Vec<_> = results.iter().filter_map(|r| r.as_ref().err()).collect();
+        assert_eq!(1, failed.len());
+        assert!(
+            failed[0].to_string().starts_with("outside.txt: "),
+            "{}",
+            failed[0]
+        );
+        assert!(!to.join("inside.txt").is_symlink());
+        assert_eq!("data", read_to_string(&to.join("inside.txt")));
+        assert_eq!("data", read_to_string(&to.join("linked-dir/file.txt")));
+        assert!(!to.join("outside.txt").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_collected_path_copy_never_writes_through_symlinks() -> Result<()> {
+        let root = tempdir();
+        let from = root.path().join("dep/dir/file.txt");
+        fs::create_dir_all(from.parent().unwrap())?;
+        write_to(&from, "data");
+        let to = root.path().join("vendor");
+        fs::create_dir_all(root.path().join("elsewhere"))?;
+        fs::create_dir_all(&to)?;
+        unix::fs::symlink(root.path().join("elsewhere"), to.join("dir"))?;
+
+        let sut = CollectedPath {
+            src: from,
+            src_rel: "dir/file.txt".into(),
+            link: None,
+        };
+        let err = sut.copy(&to, &CopyOptions::default()).unwrap_err();
+
+        assert!(
+            err.to_string().contains("outside of the vendor folder"),
+            "{err}"
+        );
+        assert!(!root.path().join("elsewhere/file.txt").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolves_inside() {
+        assert!(resolves_inside(Path::new("a/link"), Path::new("b")));
+        assert!(resolves_inside(Path::new("a/link"), Path::new("../b")));
+        assert!(!resolves_inside(Path::new("a/link"), Path::new("../../b")));
+        assert!(!resolves_inside(
+            Path::new("link"),
+            Path::new("/etc/passwd")
+        ));
+    }
+
     #[test]
     fn test_collected_path_copy() -> Result<()> {
         let from = tempdir().path().join("src/path/file.txt");
@@ -103,8 +437,9 @@ mod tests {
         let sut = CollectedPath {
             src: from,
             src_rel: "path/file.txt".into(),
+            link: None,
         };
-        sut.copy(&to_parent_dir)?;
+        sut.copy(&to_parent_dir, &CopyOptions::default())?;
         assert!(expected_to.exists());
 
         let contents = read_to_string(&expected_to);
@@ -112,4 +447,27 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_collected_path_copy_rejects_lfs_pointer() -> Result<()> {
+        let from = tempdir().path().join("src/file.bin");
+        fs::create_dir_all(from.parent().unwrap())?;
+        write_to(
+            &from,
+            "version https://git-lfs.github.com/spec/v1\noid sha256:abcd\nsize 4\n",
+        );
+
+        let to_parent_dir = tempdir().path().join("dst");
+        let sut = CollectedPath {
+            src: from,
+            src_rel: "file.bin".into(),
+            link: None,
+        };
+        let err = sut
+            .copy(&to_parent_dir, &CopyOptions::default())
+            .unwrap_err();
+
+        assert!(err.to_string().starts_with("file.bin: "), "{err}");
+        Ok(())
+    }
 }
@@ -0,0 +1,60 @@
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::format_err;
+use anyhow::Result;
+
+/// Runs a hook command through `sh`, from the `cwd` directory and with the
+/// extra environment variables `envs`.
+///
+/// # Errors
+///
+/// This function will return an error if the command cannot be spawned or
+/// it does not exit successfully.
+pub fn run(command: &str, cwd: &Path, envs: &[(&str, &str)]) -> Result<()> {
+    log::info!("\t🪝 {command}");
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .current_dir(cwd)
+        .envs(envs.iter().copied())
+        .status()
+        .map_err(|err| format_err!("cannot run hook '{command}': {err}"))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format_err!("hook '{command}' failed: {status}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_utils::read_to_string;
+    use crate::test_utils::tempdir;
+
+    #[test]
+    fn test_run_hook_with_env() -> Result<()> {
+        let cwd = tempdir();
+
+        run(
+            "echo \"$VENDIFY_URL\" > out.txt",
+            cwd.path(),
+            &[("VENDIFY_URL", "some-url")],
+        )?;
+
+        assert_eq!("some-url\n", read_to_string(&cwd.path().join("out.txt")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_hook_failure() {
+        let cwd = tempdir();
+
+        let err = run("exit 3", cwd.path(), &[]).unwrap_err();
+
+        assert!(err.to_string().starts_with("hook 'exit 3' failed"), "{err}");
+    }
+}
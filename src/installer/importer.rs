@@ -1,20 +1,57 @@
+use std::fs;
+use std::path::Path;
 use std::path::PathBuf;
 
+use anyhow::format_err;
 use anyhow::Result;
+use sha2::Digest;
+use sha2::Sha256;
 
 use super::collector::Collector;
+use super::collector::CopyOptions;
+use super::licenses;
+use super::patch;
 use super::selector::Selector;
+use crate::config::Config;
 use crate::deps::Dependency;
 use crate::deps::LockedDependency;
+use crate::deps::LockedSubmodule;
+use crate::deps::Modes;
+use crate::policy::RefnameKind;
+use crate::provenance::Origin;
+use crate::provenance::Provenance;
 use crate::repository::Repository;
+use crate::signatures;
 use crate::spec::Spec;
+use crate::transforms::Normalize;
+use crate::transforms::Transform;
+use crate::transforms::Transformer;
+use crate::versions;
+
+/// Outcome of importing a dependency into the vendor folder.
+pub struct Imported {
+    pub lock: LockedDependency,
+    pub kind: RefnameKind,
+
+    /// Vendored files, relative to the vendor folder.
+    pub files: Vec<PathBuf>,
+
+    /// License files copied from the dependency root, relative to the
+    /// vendor folder, see [`licenses::LICENSES`].
+    pub licenses: Vec<PathBuf>,
+}
 
 pub struct Importer<'a> {
     dependency: &'a Dependency,
     dependency_lock: Option<&'a LockedDependency>,
     repository: &'a Repository,
     collector: Collector,
+    transforms: Vec<Transform>,
+    normalize: Normalize,
+    provenance: &'a [Provenance],
+    fingerprint: String,
     to: PathBuf,
+    config: &'a Config,
 }
 
 impl<'a> Importer<'a> {
@@ -23,64 +60,271 @@ impl<'a> Importer<'a> {
         dependency: &'a Dependency,
         dependency_lock: Option<&'a LockedDependency>,
         repository: &'a Repository,
+        to: &Path,
+        config: &'a Config,
     ) -> Self {
-        let mut combined_filters = spec.filters.clone();
-        combined_filters.merge(&dependency.filters);
         Self {
             dependency,
             dependency_lock,
             repository,
-            collector: Selector::from(combined_filters).into(),
-            to: PathBuf::from(&spec.vendor),
+            collector: Collector::from(Selector::from(spec.effective_filters(dependency)))
+                .with_symlinks(dependency.symlinks),
+            transforms: spec
+                .transforms
+                .iter()
+                .chain(&dependency.transforms)
+                .cloned()
+                .collect(),
+            normalize: spec.effective_normalize(dependency),
+            provenance: &spec.provenance,
+            fingerprint: spec.fingerprint(dependency),
+            to: to.to_owned(),
+            config,
         }
     }
 
     /// Install copies the files of the dependency into the vendor folder.
     /// It respects the dependency lock, when passed.
-    pub fn install(&self) -> Result<LockedDependency> {
-        let refname = self.get_locked_refname();
+    pub fn install(&self) -> Result<Imported> {
+        self.fetch()?;
+        let (refname, version) = self.get_locked_refname()?;
 
         log::info!("installing {}@{}", self.dependency.url, refname);
-        self.repository.fetch(&self.dependency.refname)?;
-        self.repository.checkout(refname)?;
-        self.import()
+        self.repository.checkout(&refname)?;
+        self.import(version)
     }
 
     /// Update fetches latest changes from the git remote, against the
     /// reference. Then it installs the dependency. This will ignore the
     /// lock file and generate a new lock with the updated reference.
-    pub fn update(&self) -> Result<LockedDependency> {
-        let refname = self.dependency.refname.as_str();
+    pub fn update(&self) -> Result<Imported> {
+        log::info!(
+            "updating {}@{}",
+            self.dependency.url,
+            self.dependency.refname
+        );
+        self.fetch()?;
+        let (refname, version) = self.resolve_refname()?;
+        self.repository.checkout(&refname)?;
+        self.import(version)
+    }
 
-        log::info!("updating {}@{}", self.dependency.url, refname);
-        self.repository.fetch(refname)?;
-        self.repository.reset(refname)?;
-        self.import()
+    /// Pristine copies the files of the dependency, at the locked
+    /// reference, with the patches it already has applied, so local
+    /// modifications can be told apart from them.
+    pub fn pristine(&self) -> Result<()> {
+        self.fetch()?;
+        let (refname, version) = self.get_locked_refname()?;
+        self.repository.checkout(&refname)?;
+        if self.dependency.submodules {
+            self.repository.update_submodules()?;
+        }
+        let hasher = &mut Sha256::new();
+        self.copy_files(hasher, version.as_deref())?;
+        self.copy_licenses(hasher)?;
+        self.apply_patches(hasher)
     }
 
-    fn import(&self) -> Result<LockedDependency> {
-        self.copy_files()?;
-        let locked = self.get_locked_dependency()?;
+    fn import(&self, version: Option<String>) -> Result<Imported> {
+        let submodules = if self.dependency.submodules {
+            self.repository.update_submodules()?
+        } else {
+            vec![]
+        };
+        let signer = self.check_signatures(version.as_deref())?;
+        let mut hasher = Sha256::new();
+        let files = self.copy_files(&mut hasher, version.as_deref())?;
+        let licenses = self.copy_licenses(&mut hasher)?;
+        self.apply_patches(&mut hasher)?;
+        let mut locked = self.get_locked_dependency()?;
+        locked.hash = Some(format!("{:x}", hasher.finalize()));
+        locked.fingerprint = Some(self.fingerprint.clone());
+        locked.signer = signer;
+        if let Some(version) = &version {
+            log::info!("\t🏷️  {version}");
+        }
+        locked.version = version;
         log::info!("\t🔒 {}", locked.refname);
-        Ok(locked)
+        for (path, commit) in submodules {
+            log::info!("\t🔒 {path} {commit}");
+            locked.submodules.push(LockedSubmodule { path, commit });
+        }
+        Ok(Imported {
+            lock: locked,
+            kind: self.refname_kind()?,
+            files,
+            licenses,
+        })
     }
 
-    fn copy_files(&self) -> Result<()> {
+    /// Copies the collected files into the vendor folder. The hasher is fed
+    /// with the vendored contents and modes, and with the transforms, so
+    /// changing them changes the hash. Returns the paths of the files.
+    fn copy_files(&self, hasher: &mut Sha256, version: Option<&str>) -> Result<Vec<PathBuf>> {
+        let executables = match self.dependency.modes {
+            Modes::Git => Some(self.repository.executables()?),
+            Modes::Normalize => None,
+        };
+        let name = self.dependency.name();
+        let commit = self.repository.get_current_refname()?;
+        let origin = Origin {
+            name: &name,
+            url: &self.dependency.url,
+            commit: &commit,
+            version,
+        };
+        let opts = CopyOptions {
+            lfs_store: self.config.lfs_store.clone(),
+            transformer: Transformer::new(&self.transforms)?
+                .with_normalize(self.normalize)
+                .with_provenance(self.provenance, &origin),
+            executables,
+        };
+
+        for transform in &self.transforms {
+            hasher.update(serde_yaml::to_string(transform)?);
+        }
+        let mut files = vec![];
         for collected in self.collector.collect(&self.repository.path()) {
             log::debug!(
                 "\t.../{} -> {}",
                 collected.src_rel.display(),
                 self.to.join(&collected.src_rel).display()
             );
-            collected.copy(&self.to)?;
+            let digest = collected.copy(&self.to, &opts)?;
+            hasher.update(collected.src_rel.to_string_lossy().as_bytes());
+            hasher.update(b"\0");
+            hasher.update(digest);
+            if collected.link.is_none() {
+                hasher.update(format!(" {:o}", opts.mode(&collected.src_rel)));
+            }
+            hasher.update(b"\n");
+            files.push(collected.src_rel);
+        }
+        Ok(files)
+    }
+
+    /// Copies the license files of the dependency root, as they are, into
+    /// its own folder under [`licenses::LICENSES`], whether the filters
+    /// target them or not. The hasher is fed with their contents.
+    fn copy_licenses(&self, hasher: &mut Sha256) -> Result<Vec<PathBuf>> {
+        let folder = Path::new(licenses::LICENSES).join(self.dependency.name());
+        let mut copied = vec![];
+        for name in licenses::find(self.repository.path())? {
+            let contents = fs::read(self.repository.path().join(&name))?;
+            let rel = folder.join(&name);
+            fs::create_dir_all(self.to.join(&folder))?;
+            fs::write(self.to.join(&rel), &contents)
+                .map_err(|err| format_err!("cannot copy {}: {err}", name.display()))?;
+            log::debug!("\t📜 {}", rel.display());
+            hasher.update(rel.to_string_lossy().as_bytes());
+            hasher.update(b"\0");
+            hasher.update(format!("{:x}", Sha256::digest(&contents)));
+            hasher.update(b"\n");
+            copied.push(rel);
+        }
+        Ok(copied)
+    }
+
+    /// Applies the dependency patches on top of the vendored files, the
+    /// hasher is fed with the patches contents.
+    fn apply_patches(&self, hasher: &mut Sha256) -> Result<()> {
+        for path in &self.dependency.patches {
+            log::info!("\t🩹 {path}");
+            let diff = fs::read_to_string(path)
+                .map_err(|err| format_err!("cannot read patch {path}: {err}"))?;
+            hasher.update(&diff);
+            patch::parse(&diff)
+                .and_then(|patches| patch::apply(&patches, &self.to))
+                .map_err(|err| format_err!("cannot apply patch {path}: {err}"))?;
         }
         Ok(())
     }
 
-    fn get_locked_refname(&self) -> &str {
+    /// Fetches the refname of the dependency, or all the tags when it is a
+    /// semver range.
+    fn fetch(&self) -> Result<()> {
+        match self.dependency.version_req() {
+            Some(_) => self.repository.fetch_tags(),
+            None => self.repository.fetch(&self.dependency.refname),
+        }
+    }
+
+    /// Returns the refname to check out, and the tag for semver ranges,
+    /// taken from the lock when the dependency is locked.
+    fn get_locked_refname(&self) -> Result<(String, Option<String>)> {
         match self.dependency_lock {
-            Some(it) => &it.refname,
-            None => &self.dependency.refname,
+            Some(it) => Ok((it.refname.clone(), it.version.clone())),
+            None => self.resolve_refname(),
+        }
+    }
+
+    /// Resolves semver ranges to the tag with the highest version that
+    /// satisfies them, and branches to the commit of the remote branch. The
+    /// repository is shared by the dependencies with the same URL, so local
+    /// branches cannot be relied upon.
+    fn resolve_refname(&self) -> Result<(String, Option<String>)> {
+        let refname = &self.dependency.refname;
+        let req = match self.dependency.version_req() {
+            Some(req) => req,
+            None => return Ok((self.repository.get_remote_refname(refname)?, None)),
+        };
+
+        let tags = self.repository.tags()?;
+        let tag = versions::highest(&tags, Some(&req), self.dependency.prereleases)
+            .ok_or_else(|| format_err!("no tag satisfies {refname}"))?;
+        Ok((tag.to_string(), Some(tag.to_string())))
+    }
+
+    /// Verifies the signature of the tag the version or refname names, when
+    /// it points to the checked out commit, or else of the checked out
+    /// commit, when the dependency requires it. Returns the fingerprint of
+    /// the signing key.
+    fn check_signatures(&self, version: Option<&str>) -> Result<Option<String>> {
+        let Some(policy) = &self.dependency.verify_signatures else {
+            return Ok(None);
+        };
+
+        let commit = self.repository.get_current_refname()?;
+        let tag = version.unwrap_or(&self.dependency.refname);
+        let signed_tag = match self.repository.get_tag_commit(tag)? {
+            Some(target) if target == commit => self.repository.tag_signature(tag)?,
+            Some(target) => {
+                log::debug!("tag {tag} points to {target}, not to {commit}");
+                None
+            }
+            None => None,
+        };
+        let (what, (signature, data)) = match signed_tag {
+            Some(signed) => (format!("tag {tag}"), signed),
+            None => {
+                let signed = self
+                    .repository
+                    .commit_signature(&commit)?
+                    .ok_or_else(|| format_err!("commit {commit} is not signed"))?;
+                (format!("commit {commit}"), signed)
+            }
+        };
+        let signer = signatures::verify(policy, &signature, &data)
+            .map_err(|err| format_err!("{what}: {err}"))?;
+        log::info!("\t🔏 {what} signed by {signer}");
+        Ok(Some(signer))
+    }
+
+    /// Returns the kind of the refname of the dependency: refnames that
+    /// name a fetched tag are tags, then those that name a remote branch are
+    /// branches, otherwise they are commits.
+    fn refname_kind(&self) -> Result<RefnameKind> {
+        if let Some(kind) = RefnameKind::of(self.dependency) {
+            return Ok(kind);
+        }
+        let refname = &self.dependency.refname;
+        if self.repository.tags()?.contains(refname) {
+            Ok(RefnameKind::Tag)
+        } else if self.repository.get_remote_refname(refname).is_ok() {
+            Ok(RefnameKind::Branch)
+        } else {
+            Ok(RefnameKind::Commit)
         }
     }
 
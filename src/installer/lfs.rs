@@ -0,0 +1,164 @@
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::format_err;
+use anyhow::Result;
+use sha2::Digest;
+use sha2::Sha256;
+
+const POINTER_VERSION: &[u8] = b"version https://git-lfs.github.com/spec/v1\n";
+
+/// Pointers are tiny text files, anything bigger is regular content.
+const MAX_POINTER_SIZE: usize = 1024;
+
+/// A Git LFS pointer file, which stands in the git tree for the actual
+/// content, stored outside of the repository.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Pointer {
+    pub oid: String,
+    pub size: u64,
+}
+
+impl Pointer {
+    /// Parses the contents of a file as a LFS pointer, returns `None` when
+    /// the contents are not a pointer.
+    pub fn parse(contents: &[u8]) -> Option<Self> {
+        if contents.len() > MAX_POINTER_SIZE || !contents.starts_with(POINTER_VERSION) {
+            return None;
+        }
+
+        let text = std::str::from_utf8(contents).ok()?;
+        let mut oid = None;
+        let mut size = None;
+        for line in text.lines() {
+            if let Some(value) = line.strip_prefix("oid sha256:") {
+                oid = Some(value.trim().to_string());
+            } else if let Some(value) = line.strip_prefix("size ") {
+                size = value.trim().parse().ok();
+            }
+        }
+        Some(Self {
+            oid: oid?,
+            size: size?,
+        })
+    }
+
+    /// Returns the path of the object within a LFS object store, which uses
+    /// the same layout as `.git/lfs/objects`.
+    fn object_path(&self, store: &Path) -> Option<PathBuf> {
+        if self.oid.len() < 4 || !self.oid.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        Some(
+            store
+                .join(&self.oid[0..2])
+                .join(&self.oid[2..4])
+                .join(&self.oid),
+        )
+    }
+}
+
+/// Returns the contents to vendor. When `contents` is a LFS pointer, the
+/// object is looked up in the `store`, and its integrity is checked.
+///
+/// # Errors
+///
+/// This function will return an error when the contents are a LFS pointer
+/// that cannot be resolved.
+pub fn resolve(contents: Vec<u8>, store: Option<&Path>) -> Result<Vec<u8>> {
+    let pointer = match Pointer::parse(&contents) {
+        Some(pointer) => pointer,
+        None => return Ok(contents),
+    };
+
+    let store = store.ok_or_else(|| {
+        format_err!("file is a Git LFS pointer, set lfs_store in the user config to resolve it")
+    })?;
+    let path = pointer
+        .object_path(store)
+        .ok_or_else(|| format_err!("invalid Git LFS pointer oid '{}'", pointer.oid))?;
+    let object = fs::read(&path).map_err(|err| {
+        format_err!(
+            "cannot read Git LFS object {oid} from {store}: {err}",
+            oid = pointer.oid,
+            store = store.display(),
+        )
+    })?;
+
+    let digest = format!("{:x}", Sha256::digest(&object));
+    if digest != pointer.oid || object.len() as u64 != pointer.size {
+        return Err(format_err!(
+            "Git LFS object {} is corrupt, its contents do not match the pointer",
+            pointer.oid
+        ));
+    }
+    Ok(object)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_utils::tempdir;
+
+    fn pointer_for(data: &[u8]) -> (String, Vec<u8>) {
+        let oid = format!("{:x}", Sha256::digest(data));
+        let pointer = format!(
+            "version https://git-lfs.github.com/spec/v1\noid sha256:{oid}\nsize {}\n",
+            data.len()
+        );
+        (oid, pointer.into_bytes())
+    }
+
+    #[test]
+    fn test_pointer_parse() {
+        let (oid, pointer) = pointer_for(b"some-data");
+
+        assert_eq!(Some(Pointer { oid, size: 9 }), Pointer::parse(&pointer));
+        assert_eq!(None, Pointer::parse(b"regular file"));
+    }
+
+    #[test]
+    fn test_resolve_regular_file() -> Result<()> {
+        let actual = resolve(b"regular".to_vec(), None)?;
+
+        assert_eq!(b"regular".to_vec(), actual);
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_pointer_without_store_fails() {
+        let (_, pointer) = pointer_for(b"some-data");
+
+        let err = resolve(pointer, None).unwrap_err();
+
+        assert!(err.to_string().contains("Git LFS pointer"), "{err}");
+    }
+
+    #[test]
+    fn test_resolve_pointer_from_store() -> Result<()> {
+        let store = tempdir();
+        let (oid, pointer) = pointer_for(b"some-data");
+        let object = store.path().join(&oid[0..2]).join(&oid[2..4]);
+        fs::create_dir_all(&object)?;
+        fs::write(object.join(&oid), b"some-data")?;
+
+        let actual = resolve(pointer, Some(store.path()))?;
+
+        assert_eq!(b"some-data".to_vec(), actual);
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_pointer_from_store_detects_corruption() -> Result<()> {
+        let store = tempdir();
+        let (oid, pointer) = pointer_for(b"some-data");
+        let object = store.path().join(&oid[0..2]).join(&oid[2..4]);
+        fs::create_dir_all(&object)?;
+        fs::write(object.join(&oid), b"tampered")?;
+
+        assert!(resolve(pointer, Some(store.path())).is_err());
+        Ok(())
+    }
+}
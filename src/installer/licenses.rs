@@ -0,0 +1,112 @@
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+/// Folder of the vendor folder where the license files of each dependency
+/// are copied, under the name of the dependency.
+pub const LICENSES: &str = "LICENSES";
+
+const PREFIXES: &[&str] = &["LICENSE", "LICENCE", "COPYING", "NOTICE"];
+
+/// Markers of well known licenses, the first match wins so the most
+/// specific ones go first.
+const MARKERS: &[(&str, &[&str])] = &[
+    ("Apache-2.0", &["Apache License", "Version 2.0"]),
+    ("MPL-2.0", &["Mozilla Public License Version 2.0"]),
+    (
+        "AGPL-3.0",
+        &["GNU AFFERO GENERAL PUBLIC LICENSE", "Version 3"],
+    ),
+    (
+        "LGPL-3.0",
+        &["GNU LESSER GENERAL PUBLIC LICENSE", "Version 3"],
+    ),
+    (
+        "LGPL-2.1",
+        &["GNU LESSER GENERAL PUBLIC LICENSE", "Version 2.1"],
+    ),
+    ("GPL-3.0", &["GNU GENERAL PUBLIC LICENSE", "Version 3"]),
+    ("GPL-2.0", &["GNU GENERAL PUBLIC LICENSE", "Version 2"]),
+    ("ISC", &["ISC License"]),
+    ("MIT", &["Permission is hereby granted, free of charge"]),
+    (
+        "BSD-3-Clause",
+        &["Redistribution and use", "Neither the name"],
+    ),
+    ("BSD-2-Clause", &["Redistribution and use"]),
+    ("Unlicense", &["This is free and unencumbered software"]),
+];
+
+/// Returns the license files found in the root of the dependency, eg.
+/// `LICENSE`, `COPYING.md` or `NOTICE`, sorted by name.
+pub fn find(root: &Path) -> Result<Vec<PathBuf>> {
+    let mut found = vec![];
+    for entry in fs::read_dir(root)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_uppercase();
+        if entry.file_type()?.is_file() && PREFIXES.iter().any(|p| name.starts_with(p)) {
+            found.push(PathBuf::from(entry.file_name()));
+        }
+    }
+    found.sort();
+    Ok(found)
+}
+
+/// Guesses the SPDX identifier of the license text.
+pub fn guess(text: &str) -> Option<&'static str> {
+    MARKERS
+        .iter()
+        .find(|(_, markers)| markers.iter().all(|marker| text.contains(marker)))
+        .map(|(id, _)| *id)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_utils::tempdir;
+    use crate::test_utils::write_to;
+
+    #[test]
+    fn test_find() -> Result<()> {
+        let root = tempdir();
+        write_to(root.path().join("LICENSE"), "");
+        write_to(root.path().join("copying.md"), "");
+        write_to(root.path().join("NOTICE.txt"), "");
+        write_to(root.path().join("README.md"), "");
+        fs::create_dir(root.path().join("LICENSES"))?;
+
+        assert_eq!(
+            vec![
+                PathBuf::from("LICENSE"),
+                PathBuf::from("NOTICE.txt"),
+                PathBuf::from("copying.md")
+            ],
+            find(root.path())?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_guess() {
+        assert_eq!(
+            Some("Apache-2.0"),
+            guess("Apache License\nVersion 2.0, January 2004")
+        );
+        assert_eq!(
+            Some("MIT"),
+            guess("MIT License\n\nPermission is hereby granted, free of charge, to any person")
+        );
+        assert_eq!(
+            Some("BSD-3-Clause"),
+            guess("Redistribution and use in source and binary forms...\nNeither the name of")
+        );
+        assert_eq!(
+            Some("GPL-2.0"),
+            guess("GNU GENERAL PUBLIC LICENSE\nVersion 2, June 1991")
+        );
+        assert_eq!(None, guess("All rights reserved."));
+    }
+}
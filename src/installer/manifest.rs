@@ -0,0 +1,213 @@
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use anyhow::format_err;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
+
+use super::importer::Imported;
+use super::licenses;
+use crate::deps::Dependency;
+use crate::provenance;
+use crate::provenance::Origin;
+use crate::provenance::Provenance;
+
+/// File of the vendor folder that lists the provenance of the vendored
+/// files.
+pub const MANIFEST: &str = "MANIFEST";
+
+/// Provenance of the vendored files, written by every install.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub dependencies: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub name: String,
+    pub url: String,
+    pub commit: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+
+    /// SPDX identifier guessed from the license files, see
+    /// [`licenses::guess`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+
+    pub files: Vec<ManifestFile>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ManifestFile {
+    /// Path relative to the vendor folder.
+    pub path: String,
+
+    /// Hash of the contents, without the provenance header.
+    pub sha256: String,
+
+    /// Provenance header the file was vendored with, stripped before
+    /// hashing, so changing the template does not flag the file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub header: Option<String>,
+}
+
+/// A vendored file that no longer matches the manifest.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Mismatch {
+    pub dependency: String,
+
+    /// Path relative to the vendor folder.
+    pub path: String,
+    pub missing: bool,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let what = if self.missing {
+            "is missing"
+        } else {
+            "was modified"
+        };
+        write!(f, "{}: {} {what}", self.dependency, self.path)
+    }
+}
+
+impl Manifest {
+    /// Loads the manifest of the vendor folder.
+    pub fn load(vendor: &Path) -> Result<Self> {
+        let path = vendor.join(MANIFEST);
+        let contents = fs::read_to_string(&path).map_err(|err| {
+            format_err!("cannot read {}, run install first: {err}", path.display())
+        })?;
+        serde_yaml::from_str(&contents)
+            .map_err(|err| format_err!("cannot parse {}: {err}", path.display()))
+    }
+
+    pub fn find(&self, name: &str) -> Option<&ManifestEntry> {
+        self.dependencies
+            .iter()
+            .find(|entry| entry.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Compares the files in the vendor folder against the manifest, after
+    /// stripping the provenance header they were vendored with. Returns
+    /// every file that is missing or has been modified.
+    pub fn verify(&self, vendor: &Path) -> Vec<Mismatch> {
+        let mut mismatches = vec![];
+        for entry in &self.dependencies {
+            for file in &entry.files {
+                let missing = match hash(vendor, Path::new(&file.path), file.header.as_deref()) {
+                    Ok(sha256) if sha256 == file.sha256 => continue,
+                    Ok(_) => false,
+                    Err(_) => true,
+                };
+                mismatches.push(Mismatch {
+                    dependency: entry.name.clone(),
+                    path: file.path.clone(),
+                    missing,
+                });
+            }
+        }
+        mismatches
+    }
+}
+
+impl ManifestEntry {
+    fn origin(&self) -> Origin<'_> {
+        Origin {
+            name: &self.name,
+            url: &self.url,
+            commit: &self.commit,
+            version: self.version.as_deref(),
+        }
+    }
+}
+
+/// Writes the manifest of the imported dependencies into the vendor folder.
+/// It is written once the hooks have run, so the hashes match the files
+/// that end up vendored, files removed by the hooks are not listed.
+pub fn write(
+    vendor: &Path,
+    provenance: &[Provenance],
+    imported: &[(&Dependency, Imported)],
+) -> Result<()> {
+    let dependencies = imported
+        .iter()
+        .map(|(dep, imported)| entry(vendor, provenance, dep, imported))
+        .collect::<Result<_>>()?;
+    let manifest = Manifest { dependencies };
+    fs::write(vendor.join(MANIFEST), serde_yaml::to_string(&manifest)?)
+        .map_err(|err| format_err!("cannot write {MANIFEST}: {err}"))
+}
+
+fn entry(
+    vendor: &Path,
+    provenance: &[Provenance],
+    dep: &Dependency,
+    imported: &Imported,
+) -> Result<ManifestEntry> {
+    let mut license = BTreeSet::new();
+    for path in &imported.licenses {
+        let text = fs::read(vendor.join(path))?;
+        if let Some(id) = licenses::guess(&String::from_utf8_lossy(&text)) {
+            license.insert(id);
+        }
+    }
+
+    let mut entry = ManifestEntry {
+        name: dep.name(),
+        url: dep.url.clone(),
+        commit: imported.lock.refname.clone(),
+        version: imported.lock.version.clone(),
+        license: (!license.is_empty()).then(|| Vec::from_iter(license).join(" AND ")),
+        files: vec![],
+    };
+    let origin = entry.origin();
+    let mut files = vec![];
+    let licenses = imported.licenses.iter().map(|path| (path, None));
+    let vendored = imported
+        .files
+        .iter()
+        .map(|path| (path, provenance::header(provenance, &origin, path)));
+    for (path, header) in vendored.chain(licenses) {
+        let header = header.filter(|_| !vendor.join(path).is_symlink());
+        let sha256 = hash(vendor, path, header.as_deref())
+            .map_err(|err| format_err!("{}: {err}", path.display()))?;
+        files.push(ManifestFile {
+            path: path.to_string_lossy().to_string(),
+            sha256,
+            header,
+        });
+    }
+    entry.files = files;
+    Ok(entry)
+}
+
+/// Hashes the vendored file, without the provenance header it was
+/// vendored with, so the hash only changes when the contents do. Preserved
+/// symbolic links, which may dangle, are hashed by their target, as they
+/// are when vendored.
+fn hash(vendor: &Path, path: &Path, header: Option<&str>) -> Result<String> {
+    let path = vendor.join(path);
+    if path.is_symlink() {
+        let target = fs::read_link(&path).map_err(|err| format_err!("cannot be read: {err}"))?;
+        return Ok(format!(
+            "{:x}",
+            Sha256::digest(target.to_string_lossy().as_bytes())
+        ));
+    }
+    let contents = fs::read(path).map_err(|err| format_err!("cannot be read: {err}"))?;
+    let contents = match header {
+        Some(header) => provenance::strip(&contents, header),
+        None => Cow::Borrowed(contents.as_slice()),
+    };
+    Ok(format!("{:x}", Sha256::digest(&contents)))
+}
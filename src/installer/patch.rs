@@ -0,0 +1,507 @@
+use std::fs;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::format_err;
+use anyhow::Result;
+use git2::Patch;
+
+const NO_NEWLINE: &str = "\\ No newline at end of file";
+
+/// Lines a hunk can be away from the position stated in its header, further
+/// away matches are likely unrelated code.
+const MAX_OFFSET: usize = 100;
+
+/// Changes that a unified diff describes for a single file.
+#[derive(Debug, PartialEq, Eq)]
+pub struct FilePatch {
+    /// Path before the change, `None` when the file is created.
+    pub old: Option<PathBuf>,
+
+    /// Path after the change, `None` when the file is deleted.
+    pub new: Option<PathBuf>,
+
+    hunks: Vec<Hunk>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Hunk {
+    old_start: usize,
+    old_len: usize,
+    lines: Vec<Line>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Line {
+    Context(String),
+    Remove(String),
+    Add(String),
+}
+
+/// Parses a unified diff, as produced by `git diff` or `diff -u`, paths are
+/// expected to have one leading component, eg. `a/` and `b/`, that is
+/// stripped.
+///
+/// # Errors
+///
+/// This function will return an error if the diff is malformed.
+pub fn parse(diff: &str) -> Result<Vec<FilePatch>> {
+    let mut patches: Vec<FilePatch> = vec![];
+    let mut lines = diff.split_inclusive('\n').peekable();
+
+    while let Some(line) = lines.next() {
+        if let Some(old) = line.strip_prefix("--- ") {
+            let new = lines
+                .next()
+                .and_then(|line| line.strip_prefix("+++ "))
+                .ok_or_else(|| format_err!("malformed diff, expected '+++' after '---'"))?;
+            patches.push(FilePatch {
+                old: parse_path(old)?,
+                new: parse_path(new)?,
+                hunks: vec![],
+            });
+        } else if line.starts_with("@@ ") {
+            let patch = patches
+                .last_mut()
+                .ok_or_else(|| format_err!("malformed diff, hunk without file header"))?;
+            let (old_start, old_len, new_len) = parse_hunk_header(line)?;
+            let mut hunk = Hunk {
+                old_start,
+                old_len,
+                lines: vec![],
+            };
+            let (mut old_len, mut new_len) = (old_len, new_len);
+            while old_len > 0 || new_len > 0 {
+                let line = lines
+                    .next()
+                    .ok_or_else(|| format_err!("malformed diff, truncated hunk"))?;
+                let (kind, text) = line.split_at(1.min(line.len()));
+                let text = if text.is_empty() && kind == "\n" {
+                    "\n".to_string()
+                } else {
+                    text.to_string()
+                };
+                match kind {
+                    " " | "\n" => {
+                        old_len = old_len.saturating_sub(1);
+                        new_len = new_len.saturating_sub(1);
+                        hunk.lines.push(Line::Context(text));
+                    }
+                    "-" => {
+                        old_len = old_len.saturating_sub(1);
+                        hunk.lines.push(Line::Remove(text));
+                    }
+                    "+" => {
+                        new_len = new_len.saturating_sub(1);
+                        hunk.lines.push(Line::Add(text));
+                    }
+                    _ => return Err(format_err!("malformed diff, unexpected line '{line}'")),
+                }
+                if lines
+                    .peek()
+                    .is_some_and(|next| next.starts_with(NO_NEWLINE))
+                {
+                    lines.next();
+                    strip_newline(hunk.lines.last_mut());
+                }
+            }
+            patch.hunks.push(hunk);
+        }
+    }
+
+    Ok(patches)
+}
+
+/// Applies the patches to the files within the `root` directory.
+///
+/// # Errors
+///
+/// This function will return an error if some patch does not apply cleanly,
+/// or its paths point outside of `root`.
+pub fn apply(patches: &[FilePatch], root: &Path) -> Result<()> {
+    for patch in patches {
+        let old = match &patch.old {
+            Some(old) => fs::read_to_string(safe_join(root, old)?)
+                .map_err(|err| format_err!("cannot read {}: {err}", old.display()))?,
+            None => String::new(),
+        };
+        let contents = patch.apply_to(&old)?;
+        if let Some(new) = &patch.new {
+            let path = safe_join(root, new)?;
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, contents)?;
+        }
+        if let Some(old) = &patch.old {
+            if patch.new.as_ref() != Some(old) {
+                fs::remove_file(safe_join(root, old)?)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns a unified diff between the files of `old_root` and the files at
+/// the same relative paths in `new_root`. Files that only exist in
+/// `new_root` are not part of the diff.
+///
+/// # Errors
+///
+/// This function will return an error if the directories cannot be read.
+pub fn diff(old_root: &Path, new_root: &Path) -> Result<String> {
+    let mut diff = String::new();
+    for entry in walkdir::WalkDir::new(old_root).sort_by_file_name() {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let rel = entry.path().strip_prefix(old_root)?;
+        let old = fs::read(entry.path())?;
+        let new = fs::read(new_root.join(rel)).ok();
+        if new.as_ref() == Some(&old) {
+            continue;
+        }
+        let is_text = |data: &[u8]| std::str::from_utf8(data).is_ok();
+        if !is_text(&old) || !new.as_deref().is_none_or(is_text) {
+            log::warn!("skipping binary file {}", rel.display());
+            continue;
+        }
+
+        let mut patch = Patch::from_buffers(
+            &old,
+            Some(rel),
+            new.as_deref().unwrap_or_default(),
+            Some(rel),
+            None,
+        )?;
+        let buf = patch.to_buf()?;
+        let text = buf.as_str().unwrap_or_default();
+        if new.is_some() {
+            diff.push_str(text);
+        } else {
+            // libgit2 keeps the path on both sides, mark the file as deleted.
+            let target = format!("+++ b/{}\n", rel.display());
+            diff.push_str(&text.replacen(&target, "+++ /dev/null\n", 1));
+        }
+    }
+    Ok(diff)
+}
+
+impl FilePatch {
+    fn name(&self) -> String {
+        self.new
+            .as_ref()
+            .or(self.old.as_ref())
+            .map_or_else(String::new, |path| path.display().to_string())
+    }
+
+    /// Applies the hunks to `contents`. Hunks are looked up at the position
+    /// stated in their header, but may have moved if lines were added or
+    /// removed elsewhere in the file, in which case the closest match within
+    /// [`MAX_OFFSET`] lines wins.
+    fn apply_to(&self, contents: &str) -> Result<String> {
+        let mut lines: Vec<String> = contents.split_inclusive('\n').map(String::from).collect();
+        let mut offset: isize = 0;
+
+        for (n, hunk) in self.hunks.iter().enumerate() {
+            let old: Vec<&String> = hunk
+                .lines
+                .iter()
+                .filter_map(|line| match line {
+                    Line::Context(text) | Line::Remove(text) => Some(text),
+                    Line::Add(_) => None,
+                })
+                .collect();
+            let new: Vec<String> = hunk
+                .lines
+                .iter()
+                .filter_map(|line| match line {
+                    Line::Context(text) | Line::Add(text) => Some(text.clone()),
+                    Line::Remove(_) => None,
+                })
+                .collect();
+
+            // A hunk that removes nothing names the line it inserts after.
+            let start = if hunk.old_len == 0 {
+                hunk.old_start
+            } else {
+                hunk.old_start.saturating_sub(1)
+            };
+            #[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+            let expected = (start as isize + offset).max(0) as usize;
+            let at = find_closest(&lines, &old, expected).ok_or_else(|| {
+                format_err!(
+                    "patch does not apply to {name}, hunk #{hunk} at line {line} does not match",
+                    name = self.name(),
+                    hunk = n + 1,
+                    line = hunk.old_start,
+                )
+            })?;
+            if at != start {
+                log::warn!(
+                    "\t{name}: hunk #{hunk} applied at line {line} (offset {offset} lines)",
+                    name = self.name(),
+                    hunk = n + 1,
+                    line = at + 1,
+                    offset = at.abs_diff(start),
+                );
+            }
+
+            // Following hunks are expected to be displaced as much as this one.
+            #[allow(clippy::cast_possible_wrap)]
+            {
+                offset = (at + new.len()) as isize - (start + old.len()) as isize;
+            }
+            lines.splice(at..at + old.len(), new);
+        }
+
+        Ok(lines.concat())
+    }
+}
+
+fn find_closest(lines: &[String], old: &[&String], expected: usize) -> Option<usize> {
+    if old.len() > lines.len() {
+        return None;
+    }
+    let last = lines.len() - old.len();
+    let matches = |at: usize| {
+        lines[at..at + old.len()]
+            .iter()
+            .zip(old)
+            .all(|(a, b)| a == *b)
+    };
+
+    let expected = expected.min(last);
+    (0..=MAX_OFFSET).find_map(|distance| {
+        [
+            expected.checked_sub(distance),
+            expected.checked_add(distance),
+        ]
+        .into_iter()
+        .flatten()
+        .filter(|at| *at <= last)
+        .find(|at| matches(*at))
+    })
+}
+
+fn parse_path(header: &str) -> Result<Option<PathBuf>> {
+    let path = header.trim_end().split('\t').next().unwrap_or_default();
+    if path == "/dev/null" {
+        return Ok(None);
+    }
+    let mut components = Path::new(path).components();
+    components.next();
+    let stripped = components.as_path();
+    if stripped.as_os_str().is_empty() {
+        return Err(format_err!("malformed diff, invalid path '{path}'"));
+    }
+    Ok(Some(stripped.to_owned()))
+}
+
+fn parse_hunk_header(line: &str) -> Result<(usize, usize, usize)> {
+    let malformed = || format_err!("malformed hunk header '{}'", line.trim_end());
+    let ranges = line
+        .strip_prefix("@@ ")
+        .and_then(|rest| rest.split(" @@").next())
+        .ok_or_else(malformed)?;
+    let (old, new) = ranges.split_once(' ').ok_or_else(malformed)?;
+    let parse_range = |range: &str| -> Result<(usize, usize)> {
+        let (start, len) = range.split_once(',').unwrap_or((range, "1"));
+        Ok((
+            start.parse().map_err(|_| malformed())?,
+            len.parse().map_err(|_| malformed())?,
+        ))
+    };
+    let (old_start, old_len) = parse_range(old.strip_prefix('-').ok_or_else(malformed)?)?;
+    let (_, new_len) = parse_range(new.strip_prefix('+').ok_or_else(malformed)?)?;
+    Ok((old_start, old_len, new_len))
+}
+
+fn strip_newline(line: Option<&mut Line>) {
+    if let Some(Line::Context(text) | Line::Remove(text) | Line::Add(text)) = line {
+        if text.ends_with('\n') {
+            text.pop();
+        }
+    }
+}
+
+/// Joins `path` to `root`, refusing paths that could escape `root`.
+fn safe_join(root: &Path, path: &Path) -> Result<PathBuf> {
+    if path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        Ok(root.join(path))
+    } else {
+        Err(format_err!(
+            "patch path '{}' points outside of the vendor folder",
+            path.display()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_utils::read_to_string;
+    use crate::test_utils::tempdir;
+    use crate::test_utils::write_to;
+
+    const ORIGINAL: &str = "a\nb\nc\nd\ne\nf\n";
+
+    const DIFF: &str = "\
+diff --git a/dir/file.txt b/dir/file.txt
+index 1111111..2222222 100644
+--- a/dir/file.txt
++++ b/dir/file.txt
+@@ -2,3 +2,3 @@ a
+ b
+-c
++C
+ d
+";
+
+    #[test]
+    fn test_parse() -> Result<()> {
+        let patches = parse(DIFF)?;
+
+        assert_eq!(1, patches.len());
+        assert_eq!(Some(PathBuf::from("dir/file.txt")), patches[0].old);
+        assert_eq!(Some(PathBuf::from("dir/file.txt")), patches[0].new);
+        assert_eq!(
+            vec![Hunk {
+                old_start: 2,
+                old_len: 3,
+                lines: vec![
+                    Line::Context("b\n".into()),
+                    Line::Remove("c\n".into()),
+                    Line::Add("C\n".into()),
+                    Line::Context("d\n".into()),
+                ],
+            }],
+            patches[0].hunks
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply() -> Result<()> {
+        let root = tempdir();
+        let file = root.path().join("dir/file.txt");
+        fs::create_dir_all(file.parent().unwrap())?;
+        write_to(&file, ORIGINAL);
+
+        apply(&parse(DIFF)?, root.path())?;
+
+        assert_eq!("a\nb\nC\nd\ne\nf\n", read_to_string(&file));
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_to_moved_hunk() -> Result<()> {
+        let patches = parse(DIFF)?;
+
+        let actual = patches[0].apply_to(&format!("x\ny\n{ORIGINAL}"))?;
+
+        assert_eq!("x\ny\na\nb\nC\nd\ne\nf\n", actual);
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_to_insertion_without_context() -> Result<()> {
+        let diff = "--- a/file.txt\n+++ b/file.txt\n@@ -5,0 +6,2 @@\n+x\n+y\n";
+        let patches = parse(diff)?;
+
+        let actual = patches[0].apply_to(ORIGINAL)?;
+
+        assert_eq!("a\nb\nc\nd\ne\nx\ny\nf\n", actual);
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_to_refuses_distant_matches() -> Result<()> {
+        let patches = parse(DIFF)?;
+        let distant = format!("{}{ORIGINAL}", "x\n".repeat(MAX_OFFSET + 1));
+
+        assert!(patches[0].apply_to(&distant).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_to_fails_when_context_differs() -> Result<()> {
+        let patches = parse(DIFF)?;
+
+        let err = patches[0].apply_to("a\nb\nchanged\nd\n").unwrap_err();
+
+        assert_eq!(
+            "patch does not apply to dir/file.txt, hunk #1 at line 2 does not match",
+            err.to_string()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_new_and_deleted_files() -> Result<()> {
+        let root = tempdir();
+        write_to(root.path().join("old.txt"), "gone\n");
+        let diff = "\
+--- /dev/null
++++ b/new.txt
+@@ -0,0 +1 @@
++created
+\\ No newline at end of file
+--- a/old.txt
++++ /dev/null
+@@ -1 +0,0 @@
+-gone
+";
+
+        apply(&parse(diff)?, root.path())?;
+
+        assert_eq!("created", read_to_string(&root.path().join("new.txt")));
+        assert!(!root.path().join("old.txt").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_then_apply() -> Result<()> {
+        let (old, new) = (tempdir(), tempdir());
+        for (name, contents) in [
+            ("same.txt", "same\n"),
+            ("dir/file.txt", ORIGINAL),
+            ("gone.txt", "x\n"),
+        ] {
+            let path = old.path().join(name);
+            fs::create_dir_all(path.parent().unwrap())?;
+            write_to(path, contents);
+        }
+        fs::create_dir_all(new.path().join("dir"))?;
+        write_to(new.path().join("same.txt"), "same\n");
+        write_to(new.path().join("dir/file.txt"), "a\nb\nC\nd\ne\nf");
+        write_to(new.path().join("untracked.txt"), "");
+
+        let diff = diff(old.path(), new.path())?;
+        apply(&parse(&diff)?, old.path())?;
+
+        assert!(!diff.contains("same.txt"), "{diff}");
+        assert!(!diff.contains("untracked.txt"), "{diff}");
+        assert_eq!(
+            "a\nb\nC\nd\ne\nf",
+            read_to_string(&old.path().join("dir/file.txt"))
+        );
+        assert!(!old.path().join("gone.txt").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_refuses_paths_outside_root() -> Result<()> {
+        let root = tempdir();
+        let diff = "--- /dev/null\n+++ b/../escape.txt\n@@ -0,0 +1 @@\n+x\n";
+
+        assert!(apply(&parse(diff)?, root.path()).is_err());
+        Ok(())
+    }
+}
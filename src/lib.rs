@@ -1,16 +1,30 @@
 use std::env;
 
+#[cfg(not(unix))]
+compile_error!("vendify only supports unix targets");
+
+mod atomic;
 mod cache;
 pub mod cli;
+mod config;
 mod control;
 mod deps;
 mod filters;
 mod installer;
 mod lock;
+mod policy;
 mod preset;
+mod provenance;
 mod repository;
+mod sbom;
+mod schema;
+mod signatures;
 mod spec;
 mod spec_lock;
+mod status;
+mod transforms;
+mod urls;
+mod versions;
 mod yaml;
 
 #[cfg(test)]
@@ -54,6 +54,7 @@ impl Lock {
 
 mod unix {
     use std::fs::File;
+    use std::io;
     use std::os::unix::prelude::AsRawFd;
 
     use anyhow::format_err;
@@ -63,12 +64,17 @@ mod unix {
         flock(file, libc::LOCK_EX)
     }
 
+    /// Locks the file, retrying when a signal interrupts the wait.
     fn flock(file: &File, flag: libc::c_int) -> Result<()> {
-        let ret = unsafe { libc::flock(file.as_raw_fd(), flag) };
-        if ret < 0 {
-            Err(format_err!("cannot lock file"))
-        } else {
-            Ok(())
+        loop {
+            let ret = unsafe { libc::flock(file.as_raw_fd(), flag) };
+            if ret >= 0 {
+                return Ok(());
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(format_err!("cannot lock file: {err}"));
+            }
         }
     }
 }
@@ -0,0 +1,305 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::deps::Dependency;
+use crate::filters::Filters;
+use crate::transforms::matches_extension;
+use crate::urls;
+
+/// Rules the dependencies must follow, set in the spec or in the preset.
+/// Empty rules allow everything.
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Policy {
+    /// Patterns of the repositories that can be vendored, eg. `github.com`
+    /// or `github.com/alevinval`. They are matched segment by segment
+    /// against the start of the normalized URL, see [`urls::normalize`],
+    /// and `*` matches any text within a segment.
+    #[serde(default, skip_serializing_if = "<[_]>::is_empty")]
+    pub allowed_urls: Vec<String>,
+
+    #[serde(default, skip_serializing_if = "<[_]>::is_empty")]
+    pub forbidden_refnames: Vec<RefnameKind>,
+
+    /// Maximum size, in bytes, of the files vendored for each dependency,
+    /// license files included.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_size: Option<u64>,
+
+    /// Extensions of the files that cannot be vendored, eg. `exe`.
+    #[serde(default, skip_serializing_if = "<[_]>::is_empty")]
+    pub forbidden_extensions: Vec<String>,
+}
+
+/// What the refname of a dependency points to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RefnameKind {
+    Branch,
+    Tag,
+    Commit,
+
+    /// Semver range, resolved to a tag.
+    Range,
+}
+
+/// A rule of a policy a dependency does not follow.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Violation {
+    pub dependency: String,
+    pub message: String,
+}
+
+/// A dependency once it has been vendored, with the vendored files
+/// relative to the vendor folder.
+pub struct Vendored<'a> {
+    pub dependency: &'a Dependency,
+    pub kind: RefnameKind,
+    pub files: &'a [PathBuf],
+}
+
+impl fmt::Display for RefnameKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            Self::Branch => "branch",
+            Self::Tag => "tag",
+            Self::Commit => "commit",
+            Self::Range => "range",
+        };
+        write!(f, "{kind}")
+    }
+}
+
+impl RefnameKind {
+    /// Returns the kind of refname that can be told without fetching the
+    /// repository: semver ranges. Branches, tags and commits look the same
+    /// until the repository is fetched, even hexadecimal names can be
+    /// branches or tags.
+    pub fn of(dep: &Dependency) -> Option<Self> {
+        dep.version_req().map(|_| Self::Range)
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.dependency, self.message)
+    }
+}
+
+impl Policy {
+    /// Checks the rules that can be checked before the dependency is
+    /// fetched: its URL, the kind of refname when it can be told, see
+    /// [`RefnameKind::of`], and the extensions its filters target.
+    pub fn check_dependency(&self, dep: &Dependency, filters: &Filters) -> Vec<Violation> {
+        let mut messages = vec![];
+        if !self.allows_url(&dep.url) {
+            messages.push(format!(
+                "{} is not allowed, allowed URLs are {}",
+                dep.url,
+                self.allowed_urls.join(", ")
+            ));
+        }
+        if let Some(kind) = RefnameKind::of(dep) {
+            messages.extend(self.check_kind(&dep.refname, kind));
+        }
+        for extension in &filters.extensions {
+            if self.forbids_extension(Path::new(&format!("_.{extension}"))) {
+                messages.push(format!("extension {extension} is forbidden"));
+            }
+        }
+        violations(dep, messages)
+    }
+
+    /// Checks the rules that can only be checked once the dependency has
+    /// been vendored into `vendor`: the kind of refname, the vendored size
+    /// and the extension of each vendored file.
+    pub fn check_vendored(&self, vendor: &Path, vendored: &Vendored) -> Vec<Violation> {
+        let mut messages = vec![];
+        messages.extend(self.check_kind(&vendored.dependency.refname, vendored.kind));
+        for path in vendored.files {
+            if self.forbids_extension(path) {
+                messages.push(format!("{} has a forbidden extension", path.display()));
+            }
+        }
+        if let Some(max_size) = self.max_size {
+            let size: u64 = vendored
+                .files
+                .iter()
+                .filter_map(|path| fs::symlink_metadata(vendor.join(path)).ok())
+                .map(|metadata| metadata.len())
+                .sum();
+            if size > max_size {
+                messages.push(format!(
+                    "vendors {size} bytes, more than the maximum of {max_size}"
+                ));
+            }
+        }
+        violations(vendored.dependency, messages)
+    }
+
+    fn allows_url(&self, url: &str) -> bool {
+        let normalized = urls::normalize(url).to_ascii_lowercase();
+        let segments: Vec<_> = normalized.split('/').collect();
+        self.allowed_urls.is_empty()
+            || self.allowed_urls.iter().any(|pattern| {
+                let pattern = pattern.trim_end_matches('/').to_ascii_lowercase();
+                let patterns: Vec<_> = pattern.split('/').collect();
+                patterns.len() <= segments.len()
+                    && patterns
+                        .iter()
+                        .zip(&segments)
+                        .all(|(pattern, segment)| glob(pattern, segment))
+            })
+    }
+
+    fn check_kind(&self, refname: &str, kind: RefnameKind) -> Option<String> {
+        self.forbidden_refnames
+            .contains(&kind)
+            .then(|| format!("refname {refname} is a {kind}, which is forbidden"))
+    }
+
+    fn forbids_extension(&self, path: &Path) -> bool {
+        !self.forbidden_extensions.is_empty() && matches_extension(path, &self.forbidden_extensions)
+    }
+}
+
+fn violations(dep: &Dependency, messages: Vec<String>) -> Vec<Violation> {
+    messages
+        .into_iter()
+        .map(|message| Violation {
+            dependency: dep.name(),
+            message,
+        })
+        .collect()
+}
+
+/// Matches the text against the pattern, where `*` matches any text.
+fn glob(pattern: &str, text: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == text,
+        Some((prefix, rest)) => {
+            let Some(text) = text.strip_prefix(prefix) else {
+                return false;
+            };
+            (0..=text.len())
+                .filter(|&i| text.is_char_boundary(i))
+                .any(|i| glob(rest, &text[i..]))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::svec;
+    use crate::test_utils::tempdir;
+    use crate::test_utils::write_to;
+
+    #[test]
+    fn test_allows_url() {
+        let sut = Policy {
+            allowed_urls: svec!["github.com/alevinval", "*.example.com"],
+            ..Policy::default()
+        };
+
+        assert!(sut.allows_url("https://github.com/alevinval/vendify"));
+        assert!(sut.allows_url("git@github.com:alevinval/vendify.git"));
+        assert!(sut.allows_url("https://git.example.com/a/b"));
+        assert!(!sut.allows_url("https://github.com/alevinval-fork/vendify"));
+        assert!(!sut.allows_url("https://github.com/other/vendify"));
+        assert!(!sut.allows_url("https://example.com/a/b"));
+        assert!(Policy::default().allows_url("https://anywhere.com/a/b"));
+    }
+
+    #[test]
+    fn test_refname_kind_of() {
+        assert_eq!(
+            Some(RefnameKind::Range),
+            RefnameKind::of(&Dependency::new("url", "^1.2"))
+        );
+        assert_eq!(None, RefnameKind::of(&Dependency::new("url", "0123abcd")));
+        assert_eq!(None, RefnameKind::of(&Dependency::new("url", "master")));
+    }
+
+    #[test]
+    fn test_check_dependency_lists_every_violation() {
+        let sut = Policy {
+            allowed_urls: svec!["github.com/alevinval"],
+            forbidden_refnames: vec![RefnameKind::Range],
+            forbidden_extensions: svec!["exe"],
+            ..Policy::default()
+        };
+        let dep = Dependency::new("https://gitlab.com/a/ledger", "^1.2");
+        let filters = Filters {
+            extensions: svec!["proto", "EXE"],
+            ..Filters::new()
+        };
+
+        let messages: Vec<_> = sut
+            .check_dependency(&dep, &filters)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        assert_eq!(
+            svec![
+                "ledger: https://gitlab.com/a/ledger is not allowed, allowed URLs are \
+                 github.com/alevinval",
+                "ledger: refname ^1.2 is a range, which is forbidden",
+                "ledger: extension EXE is forbidden"
+            ],
+            messages
+        );
+    }
+
+    #[test]
+    fn test_check_vendored() {
+        let vendor = tempdir();
+        write_to(vendor.path().join("a.proto"), "12345");
+        write_to(vendor.path().join("b.exe"), "12345");
+        let sut = Policy {
+            forbidden_refnames: vec![RefnameKind::Branch],
+            max_size: Some(8),
+            forbidden_extensions: svec!["exe"],
+            ..Policy::default()
+        };
+        let dep = Dependency::new("https://github.com/a/ledger", "master");
+        let files = [PathBuf::from("a.proto"), PathBuf::from("b.exe")];
+
+        let messages: Vec<_> = sut
+            .check_vendored(
+                vendor.path(),
+                &Vendored {
+                    dependency: &dep,
+                    kind: RefnameKind::Branch,
+                    files: &files,
+                },
+            )
+            .into_iter()
+            .map(|violation| violation.message)
+            .collect();
+
+        assert_eq!(
+            svec![
+                "refname master is a branch, which is forbidden",
+                "b.exe has a forbidden extension",
+                "vendors 10 bytes, more than the maximum of 8"
+            ],
+            messages
+        );
+    }
+
+    #[test]
+    fn test_glob() {
+        assert!(glob("a*c", "abbc"));
+        assert!(glob("*", ""));
+        assert!(glob("abc", "abc"));
+        assert!(!glob("a*c", "abd"));
+        assert!(!glob("ab", "abc"));
+    }
+}
@@ -3,6 +3,7 @@ use std::fmt;
 
 use crate::deps::Dependency;
 use crate::filters::Filters;
+use crate::policy::Policy;
 
 type DependencyFiltersProvider = fn(&Dependency) -> Filters;
 
@@ -16,6 +17,7 @@ pub struct Preset {
     force_filters: bool,
     spec_filters: Filters,
     dependency_filters: DependencyFiltersProvider,
+    policy: Policy,
 }
 
 impl PartialEq for Preset {
@@ -28,6 +30,7 @@ impl PartialEq for Preset {
             && self.force_filters == other.force_filters
             && self.spec_filters == other.spec_filters
             && self.dependency_filters as usize == other.dependency_filters as usize
+            && self.policy == other.policy
     }
 }
 
@@ -43,6 +46,7 @@ impl fmt::Debug for Preset {
             .field("spec_lock", &self.spec_lock)
             .field("force_filters", &self.force_filters)
             .field("spec_filters", &self.spec_filters)
+            .field("policy", &self.policy)
             .finish()
     }
 }
@@ -92,6 +96,13 @@ impl Preset {
     pub fn force_filters(&self) -> bool {
         self.force_filters
     }
+
+    /// Policy enforced on top of the one of the spec, which the spec cannot
+    /// loosen.
+    #[must_use]
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
 }
 
 impl Default for Preset {
@@ -109,6 +120,7 @@ pub struct Builder {
     force_filters: bool,
     global_filters: Filters,
     dependency_filters: DependencyFiltersProvider,
+    policy: Policy,
 }
 
 impl Builder {
@@ -123,6 +135,7 @@ impl Builder {
             force_filters: false,
             global_filters: Filters::new(),
             dependency_filters: Self::default_dependency_filters,
+            policy: Policy::default(),
         }
     }
 
@@ -137,6 +150,7 @@ impl Builder {
             spec_filters: self.global_filters,
             dependency_filters: self.dependency_filters,
             force_filters: self.force_filters,
+            policy: self.policy,
         }
     }
 
@@ -188,6 +202,12 @@ impl Builder {
         self
     }
 
+    #[must_use]
+    pub fn policy(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+
     fn default_cache() -> String {
         home::home_dir()
             .map_or_else(
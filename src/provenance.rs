@@ -0,0 +1,190 @@
+use std::borrow::Cow;
+use std::path::Path;
+
+use anyhow::format_err;
+use anyhow::Result;
+use regex::Regex;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::transforms::matches_extension;
+
+/// Placeholders a provenance template can refer to, eg. `{url}`.
+const PLACEHOLDERS: &[&str] = &["name", "url", "commit", "version"];
+
+/// Header inserted at the top of the vendored files with one of the
+/// `extensions`, recording where they were vendored from.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Provenance {
+    /// Template of the header, `{name}`, `{url}`, `{commit}` and `{version}`
+    /// are replaced with the ones of the dependency.
+    pub template: String,
+
+    pub extensions: Vec<String>,
+}
+
+/// Where a dependency was vendored from, to render the provenance headers.
+pub struct Origin<'a> {
+    pub name: &'a str,
+    pub url: &'a str,
+    pub commit: &'a str,
+
+    /// Tag the dependency was resolved to, `{version}` falls back to the
+    /// commit without it.
+    pub version: Option<&'a str>,
+}
+
+impl Provenance {
+    pub fn render(&self, origin: &Origin) -> String {
+        self.template
+            .replace("{name}", origin.name)
+            .replace("{url}", origin.url)
+            .replace("{commit}", origin.commit)
+            .replace("{version}", origin.version.unwrap_or(origin.commit))
+    }
+
+    /// Checks the template is a single line that only refers to known
+    /// placeholders, and that it targets some extension.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error describing the first problem.
+    pub fn check(&self) -> Result<()> {
+        if self.template.trim().is_empty() || self.template.contains('\n') {
+            return Err(format_err!("template must be a single, non-empty line"));
+        }
+        if self.extensions.is_empty() {
+            return Err(format_err!(
+                "no extensions, headers are opt-in per extension"
+            ));
+        }
+        let placeholder = Regex::new(r"\{([^{}]*)\}").expect("valid placeholder regex");
+        for captures in placeholder.captures_iter(&self.template) {
+            if !PLACEHOLDERS.contains(&&captures[1]) {
+                return Err(format_err!(
+                    "unknown placeholder {}, use one of {{{}}}",
+                    &captures[0],
+                    PLACEHOLDERS.join("}, {")
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Renders the header of the file at `path`, from the first provenance that
+/// targets its extension.
+pub fn header(provenance: &[Provenance], origin: &Origin, path: &Path) -> Option<String> {
+    provenance
+        .iter()
+        .find(|it| matches_extension(path, &it.extensions))
+        .map(|it| it.render(origin))
+}
+
+/// Inserts the header line, line ending included, at the start of the
+/// contents, or after their `#!` line, so scripts keep running.
+pub fn insert(contents: &[u8], header: &[u8]) -> Vec<u8> {
+    let (shebang, rest) = contents.split_at(shebang_len(contents));
+    [shebang, header, rest].concat()
+}
+
+/// Removes the header, and the line ending that follows it, from where
+/// [`insert`] puts it. Contents without it are returned as is.
+pub fn strip<'a>(contents: &'a [u8], header: &str) -> Cow<'a, [u8]> {
+    let (shebang, rest) = contents.split_at(shebang_len(contents));
+    let Some(rest) = rest.strip_prefix(header.as_bytes()).and_then(|rest| {
+        rest.strip_prefix(b"\r\n")
+            .or_else(|| rest.strip_prefix(b"\n"))
+    }) else {
+        return Cow::Borrowed(contents);
+    };
+    if shebang.is_empty() {
+        Cow::Borrowed(rest)
+    } else {
+        Cow::Owned([shebang, rest].concat())
+    }
+}
+
+/// Length of the `#!` line the contents start with, line ending included.
+fn shebang_len(contents: &[u8]) -> usize {
+    if !contents.starts_with(b"#!") {
+        return 0;
+    }
+    contents
+        .iter()
+        .position(|&byte| byte == b'\n')
+        .map_or(0, |i| i + 1)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::svec;
+
+    const ORIGIN: Origin = Origin {
+        name: "ledger",
+        url: "https://github.com/a/ledger",
+        commit: "0123abcd",
+        version: None,
+    };
+
+    fn provenance(template: &str, extensions: Vec<String>) -> Provenance {
+        Provenance {
+            template: template.into(),
+            extensions,
+        }
+    }
+
+    #[test]
+    fn test_header() {
+        let sut = &[
+            provenance("// from {url}@{commit}", svec!["proto"]),
+            provenance("# {name} {version}", svec!["py"]),
+        ];
+
+        assert_eq!(
+            Some("// from https://github.com/a/ledger@0123abcd".to_string()),
+            header(sut, &ORIGIN, Path::new("a/b.proto"))
+        );
+        assert_eq!(
+            Some("# ledger 0123abcd".to_string()),
+            header(sut, &ORIGIN, Path::new("b.py"))
+        );
+        assert_eq!(None, header(sut, &ORIGIN, Path::new("c.txt")));
+    }
+
+    #[test]
+    fn test_strip() {
+        assert_eq!(b"data", &*strip(b"// h\ndata", "// h"));
+        assert_eq!(b"data", &*strip(b"// h\r\ndata", "// h"));
+        assert_eq!(b"// hdata", &*strip(b"// hdata", "// h"));
+        assert_eq!(b"data", &*strip(b"data", "// h"));
+        assert_eq!(b"#!/bin/sh\ndata", &*strip(b"#!/bin/sh\n# h\ndata", "# h"));
+    }
+
+    #[test]
+    fn test_insert_after_shebang() {
+        assert_eq!(b"# h\ndata".to_vec(), insert(b"data", b"# h\n"));
+        assert_eq!(
+            b"#!/usr/bin/env python\n# h\nprint()\n".to_vec(),
+            insert(b"#!/usr/bin/env python\nprint()\n", b"# h\n")
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        assert!(provenance("// {url}@{commit}", svec!["proto"])
+            .check()
+            .is_ok());
+        assert_eq!(
+            "unknown placeholder {sha}, use one of {name}, {url}, {commit}, {version}",
+            provenance("// {sha}", svec!["proto"])
+                .check()
+                .unwrap_err()
+                .to_string()
+        );
+        assert!(provenance("// {url}", vec![]).check().is_err());
+        assert!(provenance("a\nb", svec!["proto"]).check().is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::HashSet;
 use std::path::Path;
 use std::path::PathBuf;
 
@@ -5,21 +6,34 @@ use anyhow::format_err;
 use anyhow::Result;
 
 use self::git::Git;
+use crate::config::Config;
 use crate::deps::Dependency;
 
+mod credentials;
 mod git;
+mod network;
+mod proxy;
 
 pub struct Repository {
     path: PathBuf,
+    config: Config,
 }
 
 impl Repository {
     pub fn new<P: AsRef<Path>>(path: P) -> Self {
         Self {
             path: path.as_ref().to_owned(),
+            config: Config::default(),
         }
     }
 
+    /// Uses the user [`Config`] to reach the remote, eg. its credentials.
+    #[must_use]
+    pub fn with_config(mut self, config: &Config) -> Self {
+        self.config = config.clone();
+        self
+    }
+
     pub fn path(&self) -> &Path {
         &self.path
     }
@@ -29,19 +43,86 @@ impl Repository {
     }
 
     pub fn fetch(&self, refname: &str) -> Result<()> {
-        Git::fetch(&self.path, refname)
+        Git::fetch(&self.path, refname, &self.config)
+    }
+
+    /// Checks out the submodules recursively, returns the path and commit of
+    /// each one of them.
+    pub fn update_submodules(&self) -> Result<Vec<(String, String)>> {
+        Git::update_submodules(&self.path, &self.config).map(|submodules| {
+            submodules
+                .into_iter()
+                .map(|(path, oid)| (path, oid.to_string()))
+                .collect()
+        })
+    }
+
+    /// Returns the paths of the files the checked out tree records as
+    /// executable, relative to the repository root.
+    pub fn executables(&self) -> Result<HashSet<PathBuf>> {
+        Git::executables(&self.path)
+    }
+
+    pub fn fetch_tags(&self) -> Result<()> {
+        Git::fetch_tags(&self.path, &self.config)
+    }
+
+    pub fn tags(&self) -> Result<Vec<String>> {
+        Git::tags(&self.path)
+    }
+
+    /// Returns the signature of the commit and the data it signs, when it is
+    /// signed.
+    pub fn commit_signature(&self, commit: &str) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        Git::commit_signature(&self.path, commit)
+    }
+
+    /// Returns the signature of the annotated tag and the data it signs,
+    /// when it is signed.
+    pub fn tag_signature(&self, tag: &str) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        Git::tag_signature(&self.path, tag)
     }
 
-    pub fn reset(&self, refname: &str) -> Result<()> {
-        Git::reset(&self.path, refname)
+    /// Returns the commit the tag points to, when it exists.
+    pub fn get_tag_commit(&self, tag: &str) -> Result<Option<String>> {
+        Git::get_tag_commit(&self.path, tag).map(|oid| oid.map(|oid| oid.to_string()))
+    }
+
+    /// Returns the commit the remote branch `refname` points to, as of the
+    /// last fetch.
+    pub fn get_remote_refname(&self, refname: &str) -> Result<String> {
+        Git::get_remote_refname(&self.path, refname).map(|oid| oid.to_string())
+    }
+
+    /// Repacks the repository objects and prunes the unreachable ones, to
+    /// reduce the disk usage of the cache.
+    pub fn repack(&self) -> Result<()> {
+        Git::gc(&self.path)
     }
 
     pub fn get_current_refname(&self) -> Result<String> {
         Git::get_current_refname(&self.path).map(|oid| oid.to_string())
     }
 
-    pub fn ensure(self, dep: &Dependency) -> Result<Self> {
-        let result = Git::open_or_clone(&dep.url, &dep.refname, &self.path);
+    /// Checks the integrity of the repository, see [`Git::validate`].
+    pub fn validate(&self, url: &str, full: bool) -> Result<()> {
+        Git::validate(&self.path, url, full)
+    }
+
+    /// Points the origin remote to `url`, used when the URL the repository
+    /// is fetched from changes, eg. when switching to a mirror.
+    pub fn set_origin_url(&self, url: &str) -> Result<()> {
+        Git::set_origin_url(&self.path, url)
+    }
+
+    /// Ensures the repository for the dependency is available, cloning it
+    /// from `url` when necessary.
+    pub fn ensure(self, dep: &Dependency, url: &str) -> Result<Self> {
+        let branch = match dep.version_req() {
+            Some(_) => None,
+            None => Some(dep.refname.as_str()),
+        };
+        let result = Git::open_or_clone(url, branch, &self.path, &self.config);
 
         match result {
             Ok(_) => Ok(self),
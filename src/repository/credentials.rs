@@ -0,0 +1,178 @@
+use std::env;
+use std::path::Path;
+
+use anyhow::format_err;
+use git2::Cred;
+use git2::CredentialType;
+use git2::ErrorClass;
+use git2::ErrorCode;
+use git2_credentials::CredentialHandler;
+
+use crate::config::Config;
+use crate::config::Credentials;
+use crate::urls;
+
+/// User sent when neither the URL nor the credentials name one.
+const DEFAULT_USERNAME: &str = "git";
+
+/// A way to authenticate against a host, see [`Credentials`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Method {
+    SshAgent,
+    SshKey,
+    Token,
+}
+
+/// Answers the credential requests libgit2 makes during a remote
+/// operation. Hosts with [`Credentials`] try each configured method once,
+/// the other hosts fall back to the git configuration.
+pub struct Authenticator {
+    config: Config,
+    fallback: CredentialHandler,
+    tried: Vec<Method>,
+}
+
+impl Authenticator {
+    pub fn new(config: &Config, git_config: git2::Config) -> Self {
+        Self {
+            config: config.clone(),
+            fallback: CredentialHandler::new(git_config),
+            tried: vec![],
+        }
+    }
+
+    /// Returns the next credential to try for the URL, or an
+    /// authentication error when there are none left.
+    pub fn next(
+        &mut self,
+        url: &str,
+        username: Option<&str>,
+        allowed: CredentialType,
+    ) -> Result<Cred, git2::Error> {
+        let host = urls::host(url).unwrap_or_default();
+        let Some(credentials) = self.config.credentials(&host) else {
+            return self
+                .fallback
+                .try_next_credential(url, username, allowed)
+                .map_err(|err| error(err.message()));
+        };
+
+        let username = username
+            .or(credentials.username.as_deref())
+            .unwrap_or(DEFAULT_USERNAME);
+        if allowed.contains(CredentialType::USERNAME) {
+            return Cred::username(username);
+        }
+        let Some(method) = next_method(credentials, &self.tried, allowed) else {
+            return Err(error("no more credentials to try"));
+        };
+        self.tried.push(method);
+
+        match method {
+            Method::SshAgent => Cred::ssh_key_from_agent(username),
+            Method::SshKey => {
+                let passphrase = credentials
+                    .ssh_passphrase_env
+                    .as_deref()
+                    .map(var)
+                    .transpose()?;
+                let key = credentials.ssh_key.as_deref().unwrap_or(Path::new(""));
+                Cred::ssh_key(username, None, key, passphrase.as_deref())
+            }
+            Method::Token => {
+                let token = var(credentials.token_env.as_deref().unwrap_or_default())?;
+                Cred::userpass_plaintext(username, &token)
+            }
+        }
+        .map_err(|err| error(err.message()))
+    }
+}
+
+/// Turns the authentication errors of an operation on `url` into an error
+/// that names the host, other errors are returned as they are. Errors
+/// raised by the callbacks reach libgit2 formatted with their class and
+/// code, which are dropped.
+pub fn map_error(url: &str, err: git2::Error) -> anyhow::Error {
+    if err.code() != ErrorCode::Auth {
+        return err.into();
+    }
+    let host = urls::host(url).unwrap_or_else(|| url.to_string());
+    let message = err.message();
+    let message = message.split_once("; class=").map_or(message, |(it, _)| it);
+    format_err!("authentication failed for host {host}: {message}")
+}
+
+/// Returns the first configured method that has not been tried yet, and
+/// that the remote allows.
+fn next_method(
+    credentials: &Credentials,
+    tried: &[Method],
+    allowed: CredentialType,
+) -> Option<Method> {
+    let ssh = allowed.contains(CredentialType::SSH_KEY);
+    let plaintext = allowed.contains(CredentialType::USER_PASS_PLAINTEXT);
+    [
+        (Method::SshAgent, ssh && credentials.ssh_agent),
+        (Method::SshKey, ssh && credentials.ssh_key.is_some()),
+        (Method::Token, plaintext && credentials.token_env.is_some()),
+    ]
+    .into_iter()
+    .find(|(method, enabled)| *enabled && !tried.contains(method))
+    .map(|(method, _)| method)
+}
+
+fn var(name: &str) -> Result<String, git2::Error> {
+    env::var(name).map_err(|_| error(&format!("{name} is not set")))
+}
+
+fn error(message: &str) -> git2::Error {
+    git2::Error::new(ErrorCode::Auth, ErrorClass::Callback, message)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn test_next_method() {
+        let credentials = &Credentials {
+            host: "github.com".into(),
+            token_env: Some("TOKEN".into()),
+            ssh_key: Some(PathBuf::from("id_ed25519")),
+            ssh_agent: true,
+            ..Credentials::default()
+        };
+        let ssh = CredentialType::SSH_KEY;
+        let https = CredentialType::USER_PASS_PLAINTEXT;
+
+        assert_eq!(Some(Method::SshAgent), next_method(credentials, &[], ssh));
+        assert_eq!(
+            Some(Method::SshKey),
+            next_method(credentials, &[Method::SshAgent], ssh)
+        );
+        assert_eq!(
+            None,
+            next_method(credentials, &[Method::SshAgent, Method::SshKey], ssh)
+        );
+        assert_eq!(Some(Method::Token), next_method(credentials, &[], https));
+        assert_eq!(None, next_method(credentials, &[Method::Token], https));
+    }
+
+    #[test]
+    fn test_map_error() {
+        let auth = map_error("git@github.com:a/b", error("no more credentials to try"));
+        let other = map_error(
+            "git@github.com:a/b",
+            git2::Error::from_str("cannot connect"),
+        );
+
+        assert_eq!(
+            "authentication failed for host github.com: no more credentials to try",
+            auth.to_string()
+        );
+        assert_eq!("cannot connect", other.to_string());
+    }
+}
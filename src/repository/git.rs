@@ -1,17 +1,42 @@
-use std::fs::create_dir_all;
+use std::collections::HashSet;
+use std::ffi::CString;
+use std::fs;
 use std::fs::remove_dir_all;
+use std::os::unix::ffi::OsStrExt;
 use std::path::Path;
+use std::path::PathBuf;
+use std::process;
+use std::process::Command;
+use std::ptr;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
 
 use anyhow::format_err;
 use anyhow::Result;
 use git2::build::RepoBuilder;
 use git2::BranchType;
 use git2::Config;
+use git2::ErrorCode;
 use git2::FetchOptions;
+use git2::FileMode;
+use git2::ObjectType;
 use git2::Oid;
+use git2::ProxyOptions;
 use git2::RemoteCallbacks;
 use git2::Repository;
-use git2_credentials::CredentialHandler;
+use git2::SubmoduleUpdateOptions;
+use git2::TreeWalkMode;
+use git2::TreeWalkResult;
+
+use super::credentials;
+use super::credentials::Authenticator;
+use super::network;
+use super::network::Cancel;
+use super::proxy;
+use crate::atomic;
+use crate::config;
+use crate::urls;
 
 pub struct Git;
 
@@ -31,39 +56,168 @@ impl Git {
         }
     }
 
-    pub fn open_or_clone(url: &str, refname: &str, repository_path: &Path) -> Result<()> {
-        if Repository::open(repository_path).is_ok() {
-            Ok(())
-        } else {
-            if remove_dir_all(repository_path).is_ok() {
-                create_dir_all(repository_path)?;
+    /// Opens the repository, validating its integrity. When the repository
+    /// does not exist or it is not valid, it gets cloned again into a
+    /// temporary directory, which is then swapped with the broken one.
+    /// Without `branch`, the default branch of the remote is checked out.
+    pub fn open_or_clone(
+        url: &str,
+        branch: Option<&str>,
+        repository_path: &Path,
+        config: &config::Config,
+    ) -> Result<()> {
+        if repository_path.exists() {
+            match Self::validate(repository_path, url, false) {
+                Ok(()) => return Ok(()),
+                Err(err) => log::warn!("cached repository for {url} is broken, re-cloning: {err}"),
             }
-            match Self::clone(url, refname, repository_path) {
-                Ok(_) => Ok(()),
-                Err(err) => Err(format_err!(
-                    "cannot load git repository from {path}: {err}",
-                    path = repository_path.display(),
-                    err = err,
-                )),
+        }
+
+        Self::remove_stale_clones(repository_path)?;
+        let tmp_path = &Self::clone_path(repository_path);
+        match Self::clone(url, branch, tmp_path, config) {
+            Ok(_) => atomic::replace_dir(tmp_path, repository_path),
+            Err(err) => Err(format_err!(
+                "cannot load git repository from {path}: {err}",
+                path = repository_path.display(),
+                err = err,
+            )),
+        }
+    }
+
+    /// Returns a path, next to the repository, to clone it into. Each clone
+    /// gets its own, an abandoned clone may still be writing into its path,
+    /// see [`network::run`].
+    fn clone_path(repository_path: &Path) -> PathBuf {
+        static CLONES: AtomicUsize = AtomicUsize::new(0);
+        let n = CLONES.fetch_add(1, Ordering::Relaxed);
+        atomic::sibling(repository_path, &format!("tmp.{}.{n}", process::id()))
+    }
+
+    /// Removes the clones of the repository left behind by other processes,
+    /// which cannot be running anymore as the repository is locked. Clones of
+    /// this process remove themselves once they stop.
+    fn remove_stale_clones(repository_path: &Path) -> Result<()> {
+        let (Some(parent), Some(name)) = (repository_path.parent(), repository_path.file_name())
+        else {
+            return Ok(());
+        };
+        if !parent.exists() {
+            return Ok(());
+        }
+        let prefix = format!("{}.tmp", name.to_string_lossy());
+        let own = format!("{prefix}.{}.", process::id());
+        for entry in fs::read_dir(parent)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with(&prefix) && !name.starts_with(&own) {
+                log::debug!("removing stale clone {name}");
+                remove_dir_all(entry.path())?;
             }
         }
+        Ok(())
     }
 
-    pub fn clone(url: &str, refname: &str, dst: &Path) -> Result<Repository> {
+    /// Validates that the repository can be opened, that its origin remote
+    /// points to `url`, or an equivalent spelling of it, and that HEAD and the
+    /// objects it references are readable. When `full` is set, every object
+    /// in the database is read.
+    pub fn validate(repository_path: &Path, url: &str, full: bool) -> Result<()> {
+        let repository = Repository::open(repository_path)
+            .map_err(|err| format_err!("cannot open repository: {err}"))?;
+
+        let remote = repository
+            .find_remote("origin")
+            .map_err(|err| format_err!("cannot find origin remote: {err}"))?;
+        match remote.url() {
+            Some(remote_url) if urls::same(remote_url, url) => {}
+            Some(remote_url) => {
+                return Err(format_err!(
+                    "origin remote points to {remote_url}, expected {url}"
+                ))
+            }
+            None => return Err(format_err!("origin remote has no valid url")),
+        }
+
+        let tree = repository
+            .head()
+            .and_then(|head| head.peel_to_tree())
+            .map_err(|err| format_err!("cannot read HEAD: {err}"))?;
+
+        let mut blobs = vec![];
+        tree.walk(TreeWalkMode::PreOrder, |_, entry| {
+            if entry.kind() == Some(ObjectType::Blob) {
+                blobs.push(entry.id());
+            }
+            TreeWalkResult::Ok
+        })
+        .map_err(|err| format_err!("cannot read HEAD tree: {err}"))?;
+
+        let odb = repository.odb()?;
+        for oid in blobs {
+            odb.read_header(oid)
+                .map_err(|err| format_err!("cannot read object {oid}: {err}"))?;
+        }
+
+        if full {
+            let mut oids = vec![];
+            odb.foreach(|oid| {
+                oids.push(*oid);
+                true
+            })?;
+            for oid in oids {
+                odb.read(oid)
+                    .map_err(|err| format_err!("cannot read object {oid}: {err}"))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn set_origin_url(repository_path: &Path, url: &str) -> Result<()> {
+        let repository = Repository::open(repository_path)?;
+        let current = repository.find_remote("origin")?.url().map(str::to_owned);
+        if current.as_deref() != Some(url) {
+            log::debug!("setting origin of {} to {url}", repository_path.display());
+            repository.remote_set_url("origin", url)?;
+        }
+        Ok(())
+    }
+
+    pub fn clone(
+        url: &str,
+        branch: Option<&str>,
+        dst: &Path,
+        config: &config::Config,
+    ) -> Result<Repository> {
         log::info!("cloning {}...", url);
 
-        let fetch_options = Self::get_fetch_options()?;
-        match RepoBuilder::new()
-            .branch(refname)
-            .fetch_options(fetch_options)
-            .clone(url, dst)
-        {
-            Ok(it) => Ok(it),
-            Err(err) => {
-                log::error!("cannot clone {}: {}", url, err);
-                Err(err.into())
+        let result = network::run(config, url, {
+            let url = url.to_string();
+            let branch = branch.map(str::to_owned);
+            let dst = dst.to_owned();
+            let config = config.clone();
+            move |cancel| {
+                let mut builder = RepoBuilder::new();
+                if let Some(branch) = &branch {
+                    builder.branch(branch);
+                }
+                let existed = dst.exists();
+                builder
+                    .fetch_options(Self::get_fetch_options(&config, &url, cancel)?)
+                    .clone(&url, &dst)
+                    .map_err(|err| {
+                        if !existed {
+                            let _ = remove_dir_all(&dst);
+                        }
+                        credentials::map_error(&url, err)
+                    })
             }
+        });
+        if let Err(err) = &result {
+            log::error!("cannot clone {}: {}", url, err);
         }
+        result
     }
 
     pub fn checkout(repository_path: &Path, refname: &str) -> Result<()> {
@@ -84,29 +238,252 @@ impl Git {
         Ok(())
     }
 
-    pub fn fetch(repository_path: &Path, refname: &str) -> Result<()> {
+    /// Initializes and checks out the submodules recursively, at the commits
+    /// recorded by the superproject. Returns the path, relative to the
+    /// repository root, and the commit of every submodule.
+    pub fn update_submodules(
+        repository_path: &Path,
+        config: &config::Config,
+    ) -> Result<Vec<(String, Oid)>> {
+        let repository = Repository::open(repository_path)?;
+        let origin = repository
+            .find_remote("origin")
+            .ok()
+            .and_then(|remote| remote.url().map(str::to_owned))
+            .unwrap_or_default();
+        let mut updated = vec![];
+        for submodule in repository.submodules()? {
+            let path = submodule.path().to_string_lossy().to_string();
+            let name = submodule.name().unwrap_or(&path).to_string();
+            let url =
+                config.rewrite_url(&urls::resolve(&origin, submodule.url().unwrap_or_default()));
+            Self::set_submodule_url(&repository, &name, &repository_path.join(&path), &url)
+                .map_err(|err| format_err!("cannot set url of submodule {path}: {err}"))?;
+            network::run(config, &url, {
+                let repository_path = repository_path.to_owned();
+                let url = url.clone();
+                let config = config.clone();
+                move |cancel| {
+                    let repository = Repository::open(&repository_path)?;
+                    let mut opts = SubmoduleUpdateOptions::new();
+                    opts.fetch(Self::get_fetch_options(&config, &url, cancel)?);
+                    let mut submodule = repository.find_submodule(&name)?;
+                    submodule
+                        .update(true, Some(&mut opts))
+                        .map_err(|err| credentials::map_error(&url, err))
+                }
+            })
+            .map_err(|err| format_err!("cannot update submodule {path}: {err}"))?;
+            let commit = submodule
+                .head_id()
+                .ok_or_else(|| format_err!("submodule {path} has no recorded commit"))?;
+            updated.push((path.clone(), commit));
+
+            let nested = Self::update_submodules(&repository_path.join(&path), config)?;
+            updated.extend(
+                nested
+                    .into_iter()
+                    .map(|(nested_path, commit)| (format!("{path}/{nested_path}"), commit)),
+            );
+        }
+        Ok(updated)
+    }
+
+    /// Points the submodule to `url`: in the configuration of the
+    /// superproject, which is read when the submodule is cloned, and in the
+    /// origin of the submodule once it is. `.gitmodules` is part of the
+    /// checkout, so it is left untouched.
+    fn set_submodule_url(
+        repository: &Repository,
+        name: &str,
+        submodule_path: &Path,
+        url: &str,
+    ) -> Result<()> {
+        repository
+            .config()?
+            .set_str(&format!("submodule.{name}.url"), url)?;
+        if let Ok(submodule) = Repository::open(submodule_path) {
+            submodule.remote_set_url("origin", url)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the paths, relative to the repository root, of the files that
+    /// the HEAD tree records as executable. Submodules that are checked out
+    /// are walked as well.
+    pub fn executables(repository_path: &Path) -> Result<HashSet<PathBuf>> {
+        let repository = Repository::open(repository_path)?;
+        let tree = repository
+            .head()
+            .and_then(|head| head.peel_to_tree())
+            .map_err(|err| format_err!("cannot read HEAD: {err}"))?;
+
+        let mut executables = HashSet::new();
+        let mut submodules = vec![];
+        tree.walk(TreeWalkMode::PreOrder, |root, entry| {
+            let path = Path::new(root).join(entry.name().unwrap_or_default());
+            if entry.filemode() == i32::from(FileMode::BlobExecutable) {
+                executables.insert(path);
+            } else if entry.kind() == Some(ObjectType::Commit) {
+                submodules.push(path);
+            }
+            TreeWalkResult::Ok
+        })
+        .map_err(|err| format_err!("cannot read HEAD tree: {err}"))?;
+
+        for submodule in submodules {
+            let submodule_path = repository_path.join(&submodule);
+            if Repository::open(&submodule_path).is_err() {
+                continue;
+            }
+            executables.extend(
+                Self::executables(&submodule_path)?
+                    .into_iter()
+                    .map(|path| submodule.join(path)),
+            );
+        }
+        Ok(executables)
+    }
+
+    pub fn fetch(repository_path: &Path, refname: &str, config: &config::Config) -> Result<()> {
         let repository = Repository::open(repository_path)?;
         let origin_refname = format!("origin/{refname}");
         if let Err(err) = repository.find_branch(&origin_refname, BranchType::Remote) {
             return Err(format_err!("cannot find refname '{refname}': {err}"));
         }
-        let mut fo = Self::get_fetch_options()?;
-        repository
+        Self::fetch_origin(repository_path, refname, config)
+    }
+
+    /// Fetches all the tags of the origin remote.
+    pub fn fetch_tags(repository_path: &Path, config: &config::Config) -> Result<()> {
+        Self::fetch_origin(repository_path, "+refs/tags/*:refs/tags/*", config)
+    }
+
+    fn fetch_origin(repository_path: &Path, refspec: &str, config: &config::Config) -> Result<()> {
+        let url = Repository::open(repository_path)?
             .find_remote("origin")?
-            .fetch(&[refname], Some(&mut fo), None)?;
-        Ok(())
+            .url()
+            .unwrap_or_default()
+            .to_string();
+        network::run(config, &url, {
+            let repository_path = repository_path.to_owned();
+            let refspec = refspec.to_string();
+            let url = url.clone();
+            let config = config.clone();
+            move |cancel| {
+                let repository = Repository::open(&repository_path)?;
+                let mut fo = Self::get_fetch_options(&config, &url, cancel)?;
+                let mut remote = repository.find_remote("origin")?;
+                remote
+                    .fetch(&[&refspec], Some(&mut fo), None)
+                    .map_err(|err| credentials::map_error(&url, err))
+            }
+        })
     }
 
-    pub fn reset(repository_path: &Path, refname: &str) -> Result<()> {
+    /// Returns the names of the tags of the repository.
+    pub fn tags(repository_path: &Path) -> Result<Vec<String>> {
         let repository = Repository::open(repository_path)?;
-        let oid = repository.refname_to_id(&format!("refs/remotes/origin/{refname}"))?;
-        let object = repository.find_object(oid, None)?;
-        repository.reset(&object, git2::ResetType::Hard, None)?;
-        Ok(())
+        let tags = repository.tag_names(None)?;
+        Ok(tags.iter().flatten().map(String::from).collect())
+    }
+
+    /// Returns the signature of the commit and the data it signs, or `None`
+    /// when the commit is not signed.
+    pub fn commit_signature(
+        repository_path: &Path,
+        commit: &str,
+    ) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        let repository = Repository::open(repository_path)?;
+        match repository.extract_signature(&Oid::from_str(commit)?, None) {
+            Ok((signature, data)) => Ok(Some((signature.to_vec(), data.to_vec()))),
+            Err(err) if err.code() == ErrorCode::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Returns the signature of the annotated tag and the data it signs, or
+    /// `None` when the tag does not exist, is not annotated or not signed.
+    /// Tags carry their signature at the end of the message.
+    pub fn tag_signature(repository_path: &Path, tag: &str) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        let repository = Repository::open(repository_path)?;
+        let Some(oid) = repository
+            .find_reference(&format!("refs/tags/{tag}"))
+            .ok()
+            .and_then(|reference| reference.target())
+        else {
+            return Ok(None);
+        };
+        if repository.find_tag(oid).is_err() {
+            return Ok(None);
+        }
+
+        let odb = repository.odb()?;
+        let object = odb.read(oid)?;
+        let raw = object.data();
+        let start = [
+            &b"-----BEGIN PGP SIGNATURE-----"[..],
+            &b"-----BEGIN SSH SIGNATURE-----"[..],
+        ]
+        .iter()
+        .filter_map(|marker| raw.windows(marker.len()).rposition(|w| w == *marker))
+        .max();
+        Ok(start.map(|start| (raw[start..].to_vec(), raw[..start].to_vec())))
+    }
+
+    /// Returns the commit the tag points to, or `None` when there is no such
+    /// tag.
+    pub fn get_tag_commit(repository_path: &Path, tag: &str) -> Result<Option<Oid>> {
+        let repository = Repository::open(repository_path)?;
+        let Ok(reference) = repository.find_reference(&format!("refs/tags/{tag}")) else {
+            return Ok(None);
+        };
+        let commit = reference
+            .peel_to_commit()
+            .map_err(|err| format_err!("cannot read tag '{tag}': {err}"))?;
+        Ok(Some(commit.id()))
+    }
+
+    /// Returns the commit the remote branch `refname` points to.
+    pub fn get_remote_refname(repository_path: &Path, refname: &str) -> Result<Oid> {
+        let repository = Repository::open(repository_path)?;
+        let oid = repository
+            .revparse_single(&format!("refs/remotes/origin/{refname}"))
+            .and_then(|object| object.peel_to_commit())
+            .map_err(|err| format_err!("cannot find refname '{refname}': {err}"))?
+            .id();
+        Ok(oid)
+    }
+
+    /// Runs `git gc` on the repository, libgit2 does not support garbage
+    /// collection, so this relies on the git binary being available.
+    pub fn gc(repository_path: &Path) -> Result<()> {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(repository_path)
+            .args(["gc", "--quiet", "--prune=now"])
+            .output()
+            .map_err(|err| format_err!("cannot run git gc: {err}"))?;
+
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(format_err!(
+                "git gc failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))
+        }
     }
 
-    fn get_fetch_options<'cb>() -> Result<FetchOptions<'cb>> {
-        let config = match Config::open_default() {
+    /// Returns the options to fetch from `url`: the credentials, the proxy
+    /// and the TLS settings of the user config. The transfer is aborted once
+    /// it is cancelled.
+    fn get_fetch_options<'cb>(
+        config: &config::Config,
+        url: &str,
+        cancel: Cancel,
+    ) -> Result<FetchOptions<'cb>> {
+        let git_config = match Config::open_default() {
             Ok(it) => it,
             Err(err) => {
                 log::error!("cannot open git configuration: {err}");
@@ -114,19 +491,346 @@ impl Git {
             }
         };
 
-        let mut credential_helper = CredentialHandler::new(config);
+        if let Some(ca_bundle) = &config.ca_bundle {
+            Self::load_ca_bundle(ca_bundle)?;
+        }
+        let proxy = proxy::resolve(url, config, &git_config);
+        let mut authenticator = Authenticator::new(config, git_config);
 
         let mut callbacks = RemoteCallbacks::new();
-        callbacks.credentials(move |url, username, allowed| {
-            credential_helper.try_next_credential(url, username, allowed)
-        });
+        callbacks
+            .credentials(move |url, username, allowed| authenticator.next(url, username, allowed));
+        if config.insecure_tls {
+            callbacks.certificate_check(|_, _| true);
+        }
+        let transfer = cancel.clone();
+        callbacks.transfer_progress(move |_| !transfer.is_cancelled());
+        callbacks.sideband_progress(move |_| !cancel.is_cancelled());
 
         let mut fetch_options = FetchOptions::new();
         fetch_options
             .remote_callbacks(callbacks)
             .download_tags(git2::AutotagOption::All)
             .update_fetchhead(true);
+        if let Some(proxy) = proxy {
+            log::debug!("reaching {url} through {proxy}");
+            let mut proxy_options = ProxyOptions::new();
+            proxy_options.url(&proxy);
+            fetch_options.proxy_options(proxy_options);
+        }
 
         Ok(fetch_options)
     }
+
+    /// Adds the certificate authorities of the bundle to the ones libgit2
+    /// trusts. The setting is global to the process, so each bundle is only
+    /// loaded once.
+    fn load_ca_bundle(path: &Path) -> Result<()> {
+        static LOADED: Mutex<Vec<PathBuf>> = Mutex::new(vec![]);
+        let mut loaded = LOADED
+            .lock()
+            .map_err(|_| format_err!("cannot load CA bundle, a previous load panicked"))?;
+        if loaded.iter().any(|it| it == path) {
+            return Ok(());
+        }
+
+        let file = CString::new(path.as_os_str().as_bytes())?;
+        libgit2_sys::init();
+        // SAFETY: the option takes a file and a directory path, both valid
+        // C strings or null, which libgit2 copies before returning.
+        let code = unsafe {
+            libgit2_sys::git_libgit2_opts(
+                libgit2_sys::GIT_OPT_SET_SSL_CERT_LOCATIONS as libc::c_int,
+                file.as_ptr(),
+                ptr::null::<libc::c_char>(),
+            )
+        };
+        if code < 0 {
+            let err = git2::Error::last_error(code).map_or_else(
+                || "unknown error".to_string(),
+                |err| err.message().to_string(),
+            );
+            return Err(format_err!(
+                "cannot load CA bundle {}: {err}",
+                path.display()
+            ));
+        }
+        loaded.push(path.to_owned());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+    use crate::config::Credentials;
+    use crate::config::Retry;
+    use crate::config::UrlRewrite;
+    use crate::svec;
+    use crate::test_utils::commit_file;
+    use crate::test_utils::http_stub;
+    use crate::test_utils::init_upstream;
+    use crate::test_utils::tempdir;
+    use crate::test_utils::write_to;
+
+    /// Returns the names of the temporary clones next to the repository.
+    fn clones(path: &Path) -> Vec<String> {
+        fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .flatten()
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .filter(|name| name.starts_with("cache.tmp"))
+            .collect()
+    }
+
+    fn setup() -> (tempfile::TempDir, String, PathBuf) {
+        let root = tempdir();
+        let upstream = root.path().join("upstream");
+        commit_file(&init_upstream(&upstream), "file.txt", "data");
+        let url = upstream.to_string_lossy().to_string();
+        let path = root.path().join("cache");
+        (root, url, path)
+    }
+
+    #[test]
+    fn test_open_or_clone_clones_valid_repository() -> Result<()> {
+        let (_root, url, path) = &setup();
+
+        Git::open_or_clone(url, Some("master"), path, &config::Config::default())?;
+
+        Git::validate(path, url, true)?;
+        assert!(clones(path).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_or_clone_removes_stale_clones() -> Result<()> {
+        let (root, _url, path) = &setup();
+        for stale in ["tmp", "tmp.0.0"] {
+            fs::create_dir_all(atomic::sibling(path, stale))?;
+        }
+
+        let missing = root.path().join("missing").to_string_lossy().to_string();
+        let result = Git::open_or_clone(&missing, None, path, &config::Config::default());
+
+        assert!(result.is_err());
+        assert!(clones(path).is_empty(), "{:?}", clones(path));
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_fails_on_origin_mismatch() -> Result<()> {
+        let (_root, url, path) = &setup();
+        Git::open_or_clone(url, Some("master"), path, &config::Config::default())?;
+
+        let err = Git::validate(path, "other-url", false).unwrap_err();
+
+        assert!(err.to_string().contains("expected other-url"), "{err}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_or_clone_heals_origin_mismatch() -> Result<()> {
+        let (_root, url, path) = &setup();
+        Git::open_or_clone(url, Some("master"), path, &config::Config::default())?;
+        Repository::open(path)?.remote_set_url("origin", "other-url")?;
+
+        Git::open_or_clone(url, Some("master"), path, &config::Config::default())?;
+
+        Git::validate(path, url, true)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_submodules() -> Result<()> {
+        let (root, url, path) = &setup();
+        let nested = root.path().join("nested");
+        let nested_commit = commit_file(&init_upstream(&nested), "nested.txt", "nested");
+
+        let upstream = Repository::open(url)?;
+        let mut submodule =
+            upstream.submodule(&nested.to_string_lossy(), Path::new("sub"), true)?;
+        submodule.clone(None)?;
+        submodule.add_finalize()?;
+        commit_file(&upstream, "file.txt", "updated");
+
+        Git::open_or_clone(url, Some("master"), path, &config::Config::default())?;
+        let actual = Git::update_submodules(path, &config::Config::default())?;
+
+        assert_eq!(vec![("sub".to_string(), nested_commit)], actual);
+        assert!(path.join("sub/nested.txt").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_submodules_resolves_relative_and_rewritten_urls() -> Result<()> {
+        let (root, url, path) = &setup();
+        let mut upstream = Repository::open(url)?;
+        for name in ["relative", "mirrored"] {
+            let nested = root.path().join(name);
+            commit_file(&init_upstream(&nested), "nested.txt", name);
+            let mut submodule =
+                upstream.submodule(&nested.to_string_lossy(), Path::new(name), true)?;
+            submodule.clone(None)?;
+            submodule.add_finalize()?;
+        }
+        upstream.submodule_set_url("relative", "../relative")?;
+        upstream.submodule_set_url("mirrored", "https://example.invalid/mirrored")?;
+        let gitmodules = fs::read_to_string(Path::new(url).join(".gitmodules"))?;
+        commit_file(&upstream, ".gitmodules", &gitmodules);
+        let config = config::Config {
+            url_rewrites: vec![UrlRewrite {
+                base: format!("{}/", root.path().display()),
+                instead_of: svec!["https://example.invalid/"],
+            }],
+            ..config::Config::default()
+        };
+
+        Git::open_or_clone(url, Some("master"), path, &config)?;
+        Git::update_submodules(path, &config)?;
+
+        assert!(path.join("relative/nested.txt").exists());
+        assert!(path.join("mirrored/nested.txt").exists());
+        assert_eq!(
+            gitmodules,
+            fs::read_to_string(path.join(".gitmodules"))?,
+            "the checkout is left untouched"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_executables() -> Result<()> {
+        let (_root, url, path) = &setup();
+        let upstream = Repository::open(url)?;
+        let script = Path::new(url).join("run.sh");
+        write_to(&script, "#!/bin/sh");
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755))?;
+        commit_file(&upstream, "run.sh", "#!/bin/sh");
+        commit_file(&upstream, "file.txt", "plain");
+
+        Git::open_or_clone(url, Some("master"), path, &config::Config::default())?;
+        let actual = Git::executables(path)?;
+
+        assert_eq!(HashSet::from([PathBuf::from("run.sh")]), actual);
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_or_clone_heals_half_written_repository() -> Result<()> {
+        let (_root, url, path) = &setup();
+        fs::create_dir_all(path.join(".git"))?;
+        write_to(path.join(".git/HEAD"), "garbage");
+
+        Git::open_or_clone(url, Some("master"), path, &config::Config::default())?;
+
+        Git::validate(path, url, true)?;
+        assert!(!atomic::sibling(path, "old").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_clone_reports_authentication_failure() {
+        let (port, requests) = http_stub(
+            "HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: Basic \
+             realm=\"git\"\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        );
+        let root = tempdir();
+        let host = format!("127.0.0.1:{port}");
+        std::env::set_var("VENDIFY_TEST_TOKEN", "token");
+        let config = config::Config {
+            credentials: vec![Credentials {
+                host: host.clone(),
+                token_env: Some("VENDIFY_TEST_TOKEN".into()),
+                ..Credentials::default()
+            }],
+            ..config::Config::default()
+        };
+
+        let result = Git::clone(
+            &format!("http://{host}/a/b.git"),
+            None,
+            &root.path().join("clone"),
+            &config,
+        );
+
+        assert_eq!(
+            format!("authentication failed for host {host}: no more credentials to try"),
+            result.err().map(|err| err.to_string()).unwrap_or_default()
+        );
+        assert_eq!(2, requests.try_iter().count(), "tries the token once");
+    }
+
+    #[test]
+    fn test_clone_through_proxy() {
+        let (port, requests) =
+            http_stub("HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+        let root = tempdir();
+        let url = "https://vendify.invalid/a/b.git";
+        let mut config = config::Config {
+            proxy: Some(format!("http://127.0.0.1:{port}")),
+            retry: Retry {
+                attempts: 1,
+                ..Retry::default()
+            },
+            ..config::Config::default()
+        };
+
+        let proxied = Git::clone(url, None, &root.path().join("proxied"), &config);
+        config.no_proxy = vec!["vendify.invalid".into()];
+        let direct = Git::clone(url, None, &root.path().join("direct"), &config);
+
+        assert!(proxied.is_err(), "the stand-in refuses to connect");
+        assert!(direct.is_err(), "the host does not exist");
+        let requests: Vec<_> = requests.try_iter().collect();
+        assert_eq!(svec!["CONNECT vendify.invalid:443 HTTP/1.1"], requests);
+    }
+
+    #[test]
+    fn test_clone_times_out_on_hung_server() -> Result<()> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let port = listener.local_addr()?.port();
+        std::thread::spawn(move || {
+            let _connections: Vec<_> = listener.incoming().collect();
+        });
+        let root = tempdir();
+        let config = config::Config {
+            timeout_secs: Some(1),
+            ..config::Config::default()
+        };
+
+        let result = Git::clone(
+            &format!("http://127.0.0.1:{port}/a/b.git"),
+            None,
+            &root.path().join("clone"),
+            &config,
+        );
+
+        assert_eq!(
+            "timed out after 1s",
+            result.err().map(|err| err.to_string()).unwrap_or_default()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_clone_fails_on_missing_ca_bundle() {
+        let root = tempdir();
+        let config = config::Config {
+            ca_bundle: Some(root.path().join("missing.pem")),
+            ..config::Config::default()
+        };
+
+        let result = Git::clone("https://vendify.invalid/a/b", None, root.path(), &config);
+
+        assert!(
+            result
+                .err()
+                .is_some_and(|err| err.to_string().starts_with("cannot load CA bundle")),
+            "missing bundles are reported"
+        );
+    }
 }
@@ -0,0 +1,213 @@
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::format_err;
+use anyhow::Result;
+use git2::ErrorClass;
+use git2::ErrorCode;
+
+use crate::config::Config;
+use crate::urls;
+
+/// Time an abandoned operation is given to notice it has been cancelled.
+const GRACE: Duration = Duration::from_secs(5);
+
+/// Flag raised when a network operation is abandoned, the progress
+/// callbacks check it to abort the transfer.
+#[derive(Clone, Default)]
+pub struct Cancel(Arc<AtomicBool>);
+
+impl Cancel {
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Runs a network operation on the remote at `url`. It is retried with
+/// exponential backoff while it fails with transient errors, see
+/// [`is_transient`], and abandoned once it runs longer than the timeout of
+/// the config. Local repositories are not retried.
+///
+/// An abandoned operation is cancelled by its next progress callback, it is
+/// waited for during a grace period so it does not outlive the caller, and
+/// it is never retried. Operations blocked on a silent server cannot be
+/// stopped, they are left running in the background.
+pub fn run<T, F>(config: &Config, url: &str, op: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: Fn(Cancel) -> Result<T> + Send + Sync + 'static,
+{
+    let op = Arc::new(op);
+    let attempts = if urls::host(url).is_some() {
+        config.retry.attempts.max(1)
+    } else {
+        1
+    };
+    let timeout = config.timeout_secs.map(Duration::from_secs);
+    let max_backoff = Duration::from_millis(config.retry.max_backoff_ms);
+    let mut backoff = Duration::from_millis(config.retry.backoff_ms).min(max_backoff);
+
+    let mut attempt = 1;
+    loop {
+        match with_timeout(timeout, Arc::clone(&op)) {
+            Err(err) if attempt < attempts && is_transient(&err) => {
+                log::warn!("{url}: {err}, retrying in {backoff:?} ({attempt}/{attempts})");
+                thread::sleep(backoff);
+                backoff = (backoff * 2).min(max_backoff);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+fn with_timeout<T, F>(timeout: Option<Duration>, op: Arc<F>) -> Result<T>
+where
+    T: Send + 'static,
+    F: Fn(Cancel) -> Result<T> + Send + Sync + 'static,
+{
+    let cancel = Cancel::default();
+    let Some(timeout) = timeout else {
+        return op(cancel);
+    };
+
+    let (sender, receiver) = mpsc::channel();
+    let background = cancel.clone();
+    let worker = thread::spawn(move || sender.send(op(background)));
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(RecvTimeoutError::Timeout) => {
+            cancel.cancel();
+            match receiver.recv_timeout(timeout.min(GRACE)) {
+                Err(RecvTimeoutError::Timeout) => {
+                    log::warn!("cannot stop the operation, leaving it in the background");
+                }
+                _ => {
+                    let _ = worker.join();
+                }
+            }
+            Err(format_err!("timed out after {timeout:?}"))
+        }
+        Err(RecvTimeoutError::Disconnected) => Err(format_err!("network operation panicked")),
+    }
+}
+
+/// Whether the error may go away by trying again: network, OS and transport
+/// errors, and locked references, but not authentication or certificate
+/// errors, nor the errors raised by vendify itself.
+fn is_transient(err: &anyhow::Error) -> bool {
+    let Some(err) = err.downcast_ref::<git2::Error>() else {
+        return false;
+    };
+    match err.code() {
+        ErrorCode::Auth | ErrorCode::Certificate => false,
+        ErrorCode::Locked => true,
+        _ => matches!(
+            err.class(),
+            ErrorClass::Net | ErrorClass::Os | ErrorClass::Http | ErrorClass::Ssh
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::sync::atomic::AtomicU32;
+
+    use super::*;
+    use crate::config::Retry;
+
+    const URL: &str = "https://github.com/a/b";
+
+    fn config(attempts: u32) -> Config {
+        Config {
+            retry: Retry {
+                attempts,
+                backoff_ms: 1,
+                max_backoff_ms: 2,
+            },
+            ..Config::default()
+        }
+    }
+
+    fn failing(
+        code: ErrorCode,
+        class: ErrorClass,
+        fail: u32,
+    ) -> (
+        Arc<AtomicU32>,
+        impl Fn(Cancel) -> Result<u32> + Send + Sync + 'static,
+    ) {
+        let calls = Arc::new(AtomicU32::new(0));
+        let counter = Arc::clone(&calls);
+        let op = move |_| {
+            let call = counter.fetch_add(1, Ordering::Relaxed) + 1;
+            if call <= fail {
+                Err(git2::Error::new(code, class, "failure").into())
+            } else {
+                Ok(call)
+            }
+        };
+        (calls, op)
+    }
+
+    #[test]
+    fn test_run_retries_transient_errors() -> Result<()> {
+        let (calls, op) = failing(ErrorCode::GenericError, ErrorClass::Net, 2);
+
+        assert_eq!(3, run(&config(3), URL, op)?);
+        assert_eq!(3, calls.load(Ordering::Relaxed));
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_gives_up_after_the_attempts() {
+        let (calls, op) = failing(ErrorCode::GenericError, ErrorClass::Http, 5);
+
+        assert!(run(&config(2), URL, op).is_err());
+        assert_eq!(2, calls.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_run_does_not_retry_permanent_errors() {
+        let (auth, op) = failing(ErrorCode::Auth, ErrorClass::Http, 1);
+        assert!(run(&config(3), URL, op).is_err());
+        assert_eq!(1, auth.load(Ordering::Relaxed));
+
+        let (local, op) = failing(ErrorCode::GenericError, ErrorClass::Os, 1);
+        assert!(run(&config(3), "/tmp/a/b", op).is_err());
+        assert_eq!(1, local.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_with_timeout_cancels_the_operation() {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&stopped);
+
+        let result = with_timeout(
+            Some(Duration::from_millis(10)),
+            Arc::new(move |cancel: Cancel| {
+                while !cancel.is_cancelled() {
+                    thread::sleep(Duration::from_millis(1));
+                }
+                flag.store(true, Ordering::Relaxed);
+                Ok(())
+            }),
+        );
+
+        assert_eq!("timed out after 10ms", result.unwrap_err().to_string());
+        assert!(
+            stopped.load(Ordering::Relaxed),
+            "the operation stopped before returning"
+        );
+    }
+}
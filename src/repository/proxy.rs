@@ -0,0 +1,139 @@
+use std::env;
+
+use crate::config::Config;
+use crate::urls;
+
+/// Returns the proxy the remote at `url` has to be reached through, if
+/// any. Only HTTP remotes use proxies. The proxy of the user config wins,
+/// then the one of the environment (`HTTPS_PROXY` or `HTTP_PROXY`, in upper
+/// or lower case), then `http.proxy` of the git configuration. Hosts listed
+/// in `no_proxy`, or in `NO_PROXY`, are reached directly.
+pub fn resolve(url: &str, config: &Config, git_config: &git2::Config) -> Option<String> {
+    resolve_with(
+        url,
+        config,
+        |name| env::var(name).ok(),
+        || git_config.get_string("http.proxy").ok(),
+    )
+}
+
+fn resolve_with<E, G>(url: &str, config: &Config, env: E, git_proxy: G) -> Option<String>
+where
+    E: Fn(&str) -> Option<String>,
+    G: FnOnce() -> Option<String>,
+{
+    let scheme = url
+        .split_once("://")
+        .map(|(scheme, _)| scheme.to_ascii_lowercase());
+    let env_names: &[&str] = match scheme.as_deref() {
+        Some("https") => &["HTTPS_PROXY", "https_proxy"],
+        Some("http") => &["HTTP_PROXY", "http_proxy"],
+        _ => return None,
+    };
+
+    let host = urls::host(url)?;
+    let no_proxy = ["NO_PROXY", "no_proxy"]
+        .iter()
+        .filter_map(|name| env(name))
+        .flat_map(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .map(str::to_owned)
+                .collect::<Vec<_>>()
+        })
+        .chain(config.no_proxy.iter().cloned());
+    if no_proxy
+        .filter(|entry| !entry.is_empty())
+        .any(|entry| bypasses(&entry, &host))
+    {
+        return None;
+    }
+
+    config
+        .proxy
+        .clone()
+        .or_else(|| env_names.iter().find_map(|name| env(name)))
+        .or_else(git_proxy)
+        .filter(|proxy| !proxy.is_empty())
+}
+
+/// Whether the `NO_PROXY` entry covers the host: `*`, the host itself, with
+/// or without its port, or one of its parent domains.
+fn bypasses(entry: &str, host: &str) -> bool {
+    let entry = entry.trim_start_matches('.').to_ascii_lowercase();
+    let name = host.rsplit_once(':').map_or(host, |(name, _)| name);
+    entry == "*" || entry == host || entry == name || name.ends_with(&format!(".{entry}"))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::svec;
+
+    fn resolve(url: &str, config: &Config, vars: &[(&str, &str)]) -> Option<String> {
+        let vars: HashMap<_, _> = vars.iter().copied().collect();
+        resolve_with(
+            url,
+            config,
+            |name| vars.get(name).map(|value| value.to_string()),
+            || Some("http://git-config-proxy:3128".into()),
+        )
+    }
+
+    #[test]
+    fn test_resolve_precedence() {
+        let explicit = Config {
+            proxy: Some("http://explicit:3128".into()),
+            ..Config::default()
+        };
+        let vars = &[("HTTPS_PROXY", "http://env:3128")];
+
+        assert_eq!(
+            Some("http://explicit:3128".into()),
+            resolve("https://github.com/a/b", &explicit, vars)
+        );
+        assert_eq!(
+            Some("http://env:3128".into()),
+            resolve("https://github.com/a/b", &Config::default(), vars)
+        );
+        assert_eq!(
+            Some("http://git-config-proxy:3128".into()),
+            resolve("http://github.com/a/b", &Config::default(), vars)
+        );
+    }
+
+    #[test]
+    fn test_resolve_skips_non_http_remotes() {
+        let config = Config {
+            proxy: Some("http://explicit:3128".into()),
+            ..Config::default()
+        };
+
+        assert_eq!(None, resolve("git@github.com:a/b", &config, &[]));
+        assert_eq!(None, resolve("/tmp/a/b", &config, &[]));
+    }
+
+    #[test]
+    fn test_resolve_no_proxy() {
+        let config = Config {
+            proxy: Some("http://explicit:3128".into()),
+            no_proxy: svec!["corp.internal"],
+            ..Config::default()
+        };
+        let vars = &[("NO_PROXY", "localhost, .example.com")];
+
+        for url in [
+            "https://git.corp.internal/a/b",
+            "https://example.com/a/b",
+            "https://git.example.com:8443/a/b",
+            "http://localhost:8080/a/b",
+        ] {
+            assert_eq!(None, resolve(url, &config, vars), "{url}");
+        }
+        assert!(resolve("https://notexample.com/a/b", &config, vars).is_some());
+    }
+}
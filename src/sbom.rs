@@ -0,0 +1,373 @@
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use anyhow::format_err;
+use anyhow::Result;
+use serde_json::json;
+use serde_json::Value;
+use sha2::Digest;
+use sha2::Sha256;
+
+use crate::installer::manifest::Manifest;
+use crate::spec::Spec;
+use crate::spec_lock::SpecLock;
+use crate::VERSION;
+
+/// Format of the generated software bill of materials.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, clap::ValueEnum)]
+pub enum Format {
+    /// CycloneDX 1.5, JSON encoded.
+    CyclonedxJson,
+
+    /// SPDX 2.3, JSON encoded.
+    SpdxJson,
+}
+
+/// A vendored dependency, as it is described in the bill of materials.
+struct Component {
+    name: String,
+    url: String,
+    commit: String,
+    version: Option<String>,
+    license: Option<String>,
+
+    /// Path, relative to the vendor folder, and SHA-256 of the vendored
+    /// files.
+    files: Vec<(String, String)>,
+}
+
+/// Builds the bill of materials of the vendored dependencies, one component
+/// per dependency of the spec. It is generated offline: the commits come
+/// from the lock, the file list from the vendor manifest, and the hashes
+/// from the files currently in the vendor folder.
+///
+/// # Errors
+///
+/// This function will return an error if a dependency is not locked or not
+/// in the manifest, or if one of its files is missing.
+pub fn generate(spec: &Spec, spec_lock: &SpecLock, format: Format) -> Result<Value> {
+    let vendor = Path::new(&spec.vendor);
+    let manifest = Manifest::load(vendor)?;
+    let components = spec
+        .deps
+        .iter()
+        .map(|dep| {
+            let name = dep.name();
+            let lock = spec_lock
+                .get_locked_dependency(&name)
+                .ok_or_else(|| format_err!("{name} is not in the lock, run install first"))?;
+            let entry = manifest
+                .find(&name)
+                .ok_or_else(|| format_err!("{name} is not in the manifest, run install first"))?;
+            let files = entry
+                .files
+                .iter()
+                .map(|file| {
+                    let contents = fs::read(vendor.join(&file.path))
+                        .map_err(|err| format_err!("{name}: cannot read {}: {err}", file.path))?;
+                    Ok((file.path.clone(), format!("{:x}", Sha256::digest(contents))))
+                })
+                .collect::<Result<_>>()?;
+            Ok(Component {
+                name,
+                url: dep.url.clone(),
+                commit: lock.refname.clone(),
+                version: lock.version.clone(),
+                license: entry.license.clone(),
+                files,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(match format {
+        Format::CyclonedxJson => cyclonedx(&components),
+        Format::SpdxJson => spdx(&components),
+    })
+}
+
+fn cyclonedx(components: &[Component]) -> Value {
+    let components: Vec<_> = components
+        .iter()
+        .map(|component| {
+            let mut value = json!({
+                "type": "library",
+                "bom-ref": component.name,
+                "name": component.name,
+                "version": component.version.as_ref().unwrap_or(&component.commit),
+                "externalReferences": [{"type": "vcs", "url": component.url}],
+                "pedigree": {"commits": [{"uid": component.commit, "url": component.url}]},
+                "components": component.files.iter().map(|(path, sha256)| json!({
+                    "type": "file",
+                    "bom-ref": format!("{}:{path}", component.name),
+                    "name": path,
+                    "hashes": [{"alg": "SHA-256", "content": sha256}],
+                })).collect::<Vec<_>>(),
+            });
+            if let Some(license) = &component.license {
+                value["licenses"] = json!([{ "expression": license }]);
+            }
+            value
+        })
+        .collect();
+
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.5",
+        "version": 1,
+        "metadata": {
+            "tools": [{"name": "vendify", "version": VERSION}],
+        },
+        "components": components,
+    })
+}
+
+fn spdx(components: &[Component]) -> Value {
+    let mut namespace = Sha256::new();
+    let mut packages = vec![];
+    let mut files = vec![];
+    let mut relationships = vec![];
+    let mut ids = HashSet::new();
+    for component in components {
+        namespace.update(format!("{}@{}\n", component.name, component.commit));
+        let package_id = spdx_id(&mut ids, &format!("SPDXRef-Package-{}", component.name));
+        packages.push(json!({
+            "SPDXID": package_id,
+            "name": component.name,
+            "versionInfo": component.version.as_ref().unwrap_or(&component.commit),
+            "downloadLocation": format!("git+{}@{}", component.url, component.commit),
+            "filesAnalyzed": false,
+            "licenseConcluded": "NOASSERTION",
+            "licenseDeclared": component.license.as_deref().unwrap_or("NOASSERTION"),
+            "copyrightText": "NOASSERTION",
+        }));
+        relationships.push(json!({
+            "spdxElementId": "SPDXRef-DOCUMENT",
+            "relationshipType": "DESCRIBES",
+            "relatedSpdxElement": package_id,
+        }));
+        for (path, sha256) in &component.files {
+            let file_id = spdx_id(&mut ids, &format!("SPDXRef-File-{}-{path}", component.name));
+            files.push(json!({
+                "SPDXID": file_id,
+                "fileName": format!("./{path}"),
+                "checksums": [{"algorithm": "SHA256", "checksumValue": sha256}],
+                "licenseConcluded": "NOASSERTION",
+                "copyrightText": "NOASSERTION",
+            }));
+            relationships.push(json!({
+                "spdxElementId": package_id,
+                "relationshipType": "CONTAINS",
+                "relatedSpdxElement": file_id,
+            }));
+        }
+    }
+
+    json!({
+        "spdxVersion": "SPDX-2.3",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": "vendify",
+        "documentNamespace": format!(
+            "https://spdx.org/spdxdocs/vendify-{:x}",
+            namespace.finalize()
+        ),
+        "creationInfo": {
+            "created": created(),
+            "creators": [format!("Tool: vendify-{VERSION}")],
+        },
+        "packages": packages,
+        "files": files,
+        "relationships": relationships,
+    })
+}
+
+/// SPDX identifiers only allow letters, numbers, `.` and `-`, other
+/// characters are replaced with `-`. Values that end up with an identifier
+/// already in `ids` get a numeric suffix, eg. `a/b` and `a-b`.
+fn spdx_id(ids: &mut HashSet<String>, value: &str) -> String {
+    let id: String = value
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let unique = (1..)
+        .map(|n| match n {
+            1 => id.clone(),
+            n => format!("{id}-{n}"),
+        })
+        .find(|candidate| !ids.contains(candidate))
+        .unwrap_or_default();
+    ids.insert(unique.clone());
+    unique
+}
+
+/// Creation time of the document, `SOURCE_DATE_EPOCH` is honored so the
+/// document can be reproduced.
+fn created() -> String {
+    let secs = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or_default()
+        });
+    rfc3339(secs)
+}
+
+/// Formats the seconds since the epoch as a UTC timestamp, see
+/// <http://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+fn rfc3339(secs: u64) -> String {
+    let days = secs / 86400;
+    let rem = secs % 86400;
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::deps::Dependency;
+    use crate::test_utils::write_to;
+    use crate::test_utils::TestContext;
+
+    fn setup(ctx: &TestContext) -> Result<(Spec, SpecLock)> {
+        let mut spec = Spec::with_preset(&ctx.preset);
+        spec.add_dependency(Dependency::new("https://github.com/a/ledger", "master"))?;
+        let mut spec_lock = SpecLock::new();
+        let mut lock = spec.deps[0].to_locked_dependency("0123abcd");
+        lock.version = Some("v1.2.0".into());
+        spec_lock.add_locked_dependency(lock);
+
+        let vendor = Path::new(&spec.vendor);
+        fs::create_dir_all(vendor)?;
+        write_to(vendor.join("file.txt"), "data");
+        write_to(
+            vendor.join("MANIFEST"),
+            "dependencies:\n- name: ledger\n  url: https://github.com/a/ledger\n  commit: \
+             0123abcd\n  license: MIT\n  files:\n  - path: file.txt\n    sha256: stale\n",
+        );
+        Ok((spec, spec_lock))
+    }
+
+    #[test]
+    fn test_generate_cyclonedx() -> Result<()> {
+        let ctx = &TestContext::new();
+        let (spec, spec_lock) = setup(ctx)?;
+
+        let bom = generate(&spec, &spec_lock, Format::CyclonedxJson)?;
+
+        let component = &bom["components"][0];
+        assert_eq!("ledger", component["name"]);
+        assert_eq!("v1.2.0", component["version"]);
+        assert_eq!("0123abcd", component["pedigree"]["commits"][0]["uid"]);
+        assert_eq!("MIT", component["licenses"][0]["expression"]);
+        assert_eq!(
+            "3a6eb0790f39ac87c94f3856b2dd2c5d110e6811602261a9a923d3bb23adc8b7",
+            component["components"][0]["hashes"][0]["content"],
+            "hashes the vendored file, not the manifest"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_spdx() -> Result<()> {
+        let ctx = &TestContext::new();
+        let (spec, spec_lock) = setup(ctx)?;
+
+        let bom = generate(&spec, &spec_lock, Format::SpdxJson)?;
+
+        assert_eq!("SPDX-2.3", bom["spdxVersion"]);
+        assert_eq!("SPDXRef-Package-ledger", bom["packages"][0]["SPDXID"]);
+        assert_eq!(
+            "git+https://github.com/a/ledger@0123abcd",
+            bom["packages"][0]["downloadLocation"]
+        );
+        assert_eq!("./file.txt", bom["files"][0]["fileName"]);
+        assert_eq!(
+            "SPDXRef-File-ledger-file.txt",
+            bom["relationships"][1]["relatedSpdxElement"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_spdx_ids_are_unique() {
+        let component = |name: &str| Component {
+            name: name.into(),
+            url: "https://github.com/a/b".into(),
+            commit: "0123abcd".into(),
+            version: None,
+            license: None,
+            files: vec![
+                ("a/b.txt".into(), "x".into()),
+                ("a-b.txt".into(), "x".into()),
+            ],
+        };
+
+        let bom = spdx(&[component("foo_bar"), component("foo-bar")]);
+
+        let ids: Vec<&str> = bom["packages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .chain(bom["files"].as_array().unwrap())
+            .map(|it| it["SPDXID"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            vec![
+                "SPDXRef-Package-foo-bar",
+                "SPDXRef-Package-foo-bar-2",
+                "SPDXRef-File-foo-bar-a-b.txt",
+                "SPDXRef-File-foo-bar-a-b.txt-2",
+                "SPDXRef-File-foo-bar-a-b.txt-3",
+                "SPDXRef-File-foo-bar-a-b.txt-4",
+            ],
+            ids
+        );
+    }
+
+    #[test]
+    fn test_generate_requires_lock() -> Result<()> {
+        let ctx = &TestContext::new();
+        let (spec, _) = setup(ctx)?;
+
+        let result = generate(&spec, &SpecLock::new(), Format::SpdxJson);
+
+        assert_eq!(
+            "ledger is not in the lock, run install first",
+            result.unwrap_err().to_string()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_rfc3339() {
+        assert_eq!("1970-01-01T00:00:00Z", rfc3339(0));
+        assert_eq!("2024-02-29T12:34:56Z", rfc3339(1_709_210_096));
+    }
+}
@@ -0,0 +1,183 @@
+use anyhow::format_err;
+use anyhow::Result;
+use serde_yaml::Mapping;
+use serde_yaml::Value;
+
+use crate::deps::name_from_url;
+
+/// Key holding the schema version of a document, documents without it
+/// predate schema versioning and are at version 0.
+const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+/// Upgrades a document from the previous schema version to the next one.
+pub type Migration = fn(&mut Mapping) -> Result<()>;
+
+/// Schema of a kind of document, with the ordered migrations that upgrade
+/// older documents, the migration at index `i` upgrades a document from
+/// version `i` to version `i + 1`.
+pub struct Schema {
+    name: &'static str,
+    migrations: &'static [Migration],
+}
+
+/// Schema of the spec file.
+pub const SPEC: Schema = Schema {
+    name: "spec",
+    migrations: &[introduce_schema_version],
+};
+
+/// Schema of the lock file.
+pub const LOCK: Schema = Schema {
+    name: "lock",
+    migrations: &[introduce_schema_version, name_locked_dependencies],
+};
+
+impl Schema {
+    /// Returns the current version of the schema.
+    pub fn version(&self) -> u32 {
+        self.migrations.len() as u32
+    }
+
+    /// Upgrades the document to the current version, running the
+    /// migrations from its version onwards.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the document is not a mapping,
+    /// a migration fails, or the document was written by a newer vendify.
+    pub fn migrate(&self, mut document: Value) -> Result<Value> {
+        let mapping = document
+            .as_mapping_mut()
+            .ok_or_else(|| format_err!("{} is not a mapping", self.name))?;
+        let from = match mapping.get(SCHEMA_VERSION_KEY) {
+            None => 0,
+            Some(value) => value
+                .as_u64()
+                .and_then(|version| u32::try_from(version).ok())
+                .ok_or_else(|| format_err!("invalid {SCHEMA_VERSION_KEY} in {}", self.name))?,
+        };
+
+        if from > self.version() {
+            let written_by = mapping
+                .get("version")
+                .and_then(Value::as_str)
+                .unwrap_or("unknown");
+            return Err(format_err!(
+                "{name} has schema version {from} and was written by vendify {written_by}, this \
+                 vendify supports up to version {supported}, please upgrade vendify",
+                name = self.name,
+                supported = self.version(),
+            ));
+        }
+
+        for (version, migration) in self.migrations.iter().enumerate().skip(from as usize) {
+            log::debug!("migrating {} to schema version {}", self.name, version + 1);
+            migration(mapping)?;
+            mapping.insert(SCHEMA_VERSION_KEY.into(), (version as u64 + 1).into());
+        }
+        Ok(document)
+    }
+}
+
+/// Version 1 introduces the schema version, the fields are unchanged.
+fn introduce_schema_version(_: &mut Mapping) -> Result<()> {
+    Ok(())
+}
+
+/// Version 2 identifies the locked dependencies by name instead of URL, the
+/// names are derived from the URLs, as dependencies had no name before. They
+/// may not match the spec, see [`crate::spec_lock::SpecLock::adopt_names`].
+fn name_locked_dependencies(lock: &mut Mapping) -> Result<()> {
+    let deps = match lock.get_mut("deps").and_then(Value::as_sequence_mut) {
+        Some(deps) => deps,
+        None => return Ok(()),
+    };
+    for dep in deps.iter_mut().filter_map(Value::as_mapping_mut) {
+        if dep.contains_key("name") {
+            continue;
+        }
+        let url = dep
+            .get("url")
+            .and_then(Value::as_str)
+            .ok_or_else(|| format_err!("locked dependency without url"))?;
+        let name = name_from_url(url);
+        dep.insert("name".into(), name.into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn rename_a_to_b(mapping: &mut Mapping) -> Result<()> {
+        if let Some(value) = mapping.remove("a") {
+            mapping.insert("b".into(), value);
+        }
+        Ok(())
+    }
+
+    fn rename_b_to_c(mapping: &mut Mapping) -> Result<()> {
+        if let Some(value) = mapping.remove("b") {
+            mapping.insert("c".into(), value);
+        }
+        Ok(())
+    }
+
+    const TEST: Schema = Schema {
+        name: "test",
+        migrations: &[rename_a_to_b, rename_b_to_c],
+    };
+
+    fn parse(yaml: &str) -> Value {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_schema_migrate_runs_migrations_in_order() -> Result<()> {
+        let actual = TEST.migrate(parse("a: 1"))?;
+
+        assert_eq!(parse("c: 1\nschema_version: 2"), actual);
+        Ok(())
+    }
+
+    #[test]
+    fn test_schema_migrate_skips_applied_migrations() -> Result<()> {
+        let actual = TEST.migrate(parse("a: 1\nb: 2\nschema_version: 1"))?;
+
+        assert_eq!(parse("a: 1\nc: 2\nschema_version: 2"), actual);
+        Ok(())
+    }
+
+    #[test]
+    fn test_schema_migrate_rejects_newer_documents() {
+        let err = TEST
+            .migrate(parse("version: 9.0.0\nschema_version: 3"))
+            .unwrap_err();
+
+        assert!(err.to_string().contains("vendify 9.0.0"), "{err}");
+    }
+
+    #[test]
+    fn test_lock_migrate_names_dependencies() -> Result<()> {
+        let actual = LOCK.migrate(parse(
+            "version: 0.1.0\ndeps:\n- url: https://github.com/a/ledger.git\n  refname: abc\n",
+        ))?;
+
+        assert_eq!(
+            parse(
+                "version: 0.1.0\ndeps:\n- url: https://github.com/a/ledger.git\n  refname: abc\n  \
+                 name: ledger\nschema_version: 2"
+            ),
+            actual
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_schema_migrate_rejects_invalid_documents() {
+        assert!(TEST.migrate(parse("- a")).is_err());
+        assert!(TEST.migrate(parse("schema_version: x")).is_err());
+    }
+}
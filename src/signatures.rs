@@ -0,0 +1,200 @@
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::Command;
+use std::process::Output;
+use std::process::Stdio;
+
+use anyhow::format_err;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+
+const SSH_SIGNATURE: &[u8] = b"-----BEGIN SSH SIGNATURE-----";
+const PGP_SIGNATURE: &[u8] = b"-----BEGIN PGP SIGNATURE-----";
+
+/// Namespace git uses when signing commits and tags with SSH keys.
+const SSH_NAMESPACE: &str = "git";
+
+/// Keys allowed to sign the revisions of a dependency, stored in the
+/// repository so signatures are verified offline. Paths are relative to the
+/// spec.
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct SignaturePolicy {
+    /// Armored GPG public keys.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gpg_keys: Option<String>,
+
+    /// SSH allowed signers file, see `ssh-keygen(1)`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssh_allowed_signers: Option<String>,
+}
+
+/// Verifies that `signature` is a valid GPG or SSH signature of `data`, made
+/// by one of the keys the policy allows. Returns the fingerprint of the
+/// signing key.
+///
+/// # Errors
+///
+/// This function will return an error if the signature is not valid, the
+/// policy has no keys of its kind, or `gpg` or `ssh-keygen` cannot be run.
+pub fn verify(policy: &SignaturePolicy, signature: &[u8], data: &[u8]) -> Result<String> {
+    let scratch =
+        tempfile::tempdir().map_err(|err| format_err!("cannot create temporary folder: {err}"))?;
+    let signature_path = scratch.path().join("signature");
+    fs::write(&signature_path, signature)?;
+
+    if signature.starts_with(SSH_SIGNATURE) {
+        let allowed_signers = policy
+            .ssh_allowed_signers
+            .as_ref()
+            .ok_or_else(|| format_err!("signed with SSH, but no ssh_allowed_signers are set"))?;
+        verify_ssh(Path::new(allowed_signers), &signature_path, data)
+    } else if signature.starts_with(PGP_SIGNATURE) {
+        let keys = policy
+            .gpg_keys
+            .as_ref()
+            .ok_or_else(|| format_err!("signed with GPG, but no gpg_keys are set"))?;
+        verify_gpg(Path::new(keys), scratch.path(), &signature_path, data)
+    } else {
+        Err(format_err!("unsupported signature format"))
+    }
+}
+
+fn verify_ssh(allowed_signers: &Path, signature: &Path, data: &[u8]) -> Result<String> {
+    let principals = run(
+        Command::new("ssh-keygen")
+            .args(["-Y", "find-principals", "-f"])
+            .arg(allowed_signers)
+            .arg("-s")
+            .arg(signature),
+        None,
+    )?;
+    let principal = String::from_utf8_lossy(&principals.stdout)
+        .lines()
+        .next()
+        .map(str::to_owned)
+        .filter(|_| principals.status.success())
+        .ok_or_else(|| format_err!("signing key is not in the allowed signers"))?;
+
+    let output = run(
+        Command::new("ssh-keygen")
+            .args(["-Y", "verify", "-n", SSH_NAMESPACE, "-f"])
+            .arg(allowed_signers)
+            .args(["-I", &principal, "-s"])
+            .arg(signature),
+        Some(data),
+    )?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    match stdout.split_whitespace().last() {
+        Some(fingerprint) if output.status.success() => Ok(fingerprint.to_string()),
+        _ => Err(format_err!(
+            "invalid signature: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+    }
+}
+
+/// Verifies with a throwaway GPG home, so only the allowed keys are trusted
+/// and the keyring of the user is never touched.
+fn verify_gpg(keys: &Path, scratch: &Path, signature: &Path, data: &[u8]) -> Result<String> {
+    let home = scratch.join("gnupg");
+    fs::create_dir(&home)?;
+    fs::set_permissions(&home, fs::Permissions::from_mode(0o700))?;
+    let data_path = scratch.join("data");
+    fs::write(&data_path, data)?;
+
+    let import = run(
+        Command::new("gpg")
+            .arg("--homedir")
+            .arg(&home)
+            .args(["--batch", "--quiet", "--import"])
+            .arg(keys),
+        None,
+    )?;
+    if !import.status.success() {
+        return Err(format_err!(
+            "cannot import {}: {}",
+            keys.display(),
+            String::from_utf8_lossy(&import.stderr).trim()
+        ));
+    }
+
+    let output = run(
+        Command::new("gpg")
+            .arg("--homedir")
+            .arg(&home)
+            .args(["--batch", "--status-fd", "1", "--verify"])
+            .arg(signature)
+            .arg(&data_path),
+        None,
+    )?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix("[GNUPG:] VALIDSIG "))
+        .and_then(|rest| rest.split_whitespace().next())
+        .map(str::to_owned)
+        .filter(|_| output.status.success())
+        .ok_or_else(|| format_err!("signature is not valid for the allowed keys"))
+}
+
+fn run(command: &mut Command, stdin: Option<&[u8]>) -> Result<Output> {
+    let program = command.get_program().to_string_lossy().to_string();
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| format_err!("cannot run {program}: {err}"))?;
+    if let Some(mut pipe) = child.stdin.take() {
+        pipe.write_all(stdin.unwrap_or_default())?;
+    }
+    Ok(child.wait_with_output()?)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_utils::ssh_key;
+    use crate::test_utils::ssh_sign;
+    use crate::test_utils::tempdir;
+
+    #[test]
+    fn test_verify_ssh() -> Result<()> {
+        let root = tempdir();
+        let (key, allowed_signers) = ssh_key(root.path(), "alice");
+        let (other, _) = ssh_key(&root.path().join("other"), "mallory");
+        let policy = SignaturePolicy {
+            ssh_allowed_signers: Some(allowed_signers.to_string_lossy().to_string()),
+            ..SignaturePolicy::default()
+        };
+
+        let fingerprint = verify(&policy, &ssh_sign(&key, b"data"), b"data")?;
+        assert!(fingerprint.starts_with("SHA256:"), "{fingerprint}");
+
+        assert!(verify(&policy, &ssh_sign(&key, b"data"), b"tampered").is_err());
+        assert_eq!(
+            "signing key is not in the allowed signers",
+            verify(&policy, &ssh_sign(&other, b"data"), b"data")
+                .unwrap_err()
+                .to_string()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_requires_keys_of_the_kind() {
+        let result = verify(
+            &SignaturePolicy::default(),
+            b"-----BEGIN PGP SIGNATURE-----\n",
+            b"data",
+        );
+
+        assert_eq!(
+            "signed with GPG, but no gpg_keys are set",
+            result.unwrap_err().to_string()
+        );
+    }
+}
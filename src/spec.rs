@@ -1,20 +1,38 @@
 use std::fmt::Debug;
 
+use anyhow::format_err;
 use anyhow::Result;
 use serde::Deserialize;
 use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
 
+use crate::deps::is_default;
 use crate::deps::Dependency;
 use crate::filters::Filters;
+use crate::policy::Policy;
+use crate::policy::Violation;
 use crate::preset::Preset;
+use crate::provenance::Provenance;
+use crate::schema;
+use crate::transforms::Normalize;
+use crate::transforms::Transform;
+use crate::urls;
+use crate::versions;
 use crate::yaml;
 use crate::VERSION;
 
+mod lint;
+
 #[derive(Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Spec {
     /// Version that was used to generate the spec.
     pub version: String,
 
+    /// Version of the schema of the spec, see [`schema::SPEC`].
+    #[serde(default)]
+    pub schema_version: u32,
+
     // Name of the preset used to generate this spec file.
     #[serde(default, rename = "preset")]
     preset_name: String,
@@ -25,6 +43,30 @@ pub struct Spec {
     #[serde(flatten)]
     pub filters: Filters,
 
+    /// Transforms applied to the files of every dependency, before the
+    /// dependency transforms.
+    #[serde(default, skip_serializing_if = "<[_]>::is_empty")]
+    pub transforms: Vec<Transform>,
+
+    /// Normalization of the text files of every dependency, unless the
+    /// dependency sets its own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub normalize: Option<Normalize>,
+
+    /// Headers inserted at the top of the vendored files, recording where
+    /// they come from.
+    #[serde(default, skip_serializing_if = "<[_]>::is_empty")]
+    pub provenance: Vec<Provenance>,
+
+    /// Rules the dependencies must follow, see [`Policy`].
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub policy: Policy,
+
+    /// Commands run in the vendor directory after all the dependencies have
+    /// been vendored.
+    #[serde(default, skip_serializing_if = "<[_]>::is_empty")]
+    pub hooks: Vec<String>,
+
     /// List of dependencies.
     pub deps: Vec<Dependency>,
 
@@ -36,8 +78,14 @@ impl Spec {
     pub fn with_preset(preset: &Preset) -> Self {
         let mut spec = Self {
             version: VERSION.to_string(),
+            schema_version: schema::SPEC.version(),
             vendor: String::new(),
             filters: Filters::new(),
+            transforms: vec![],
+            normalize: None,
+            provenance: vec![],
+            policy: Policy::default(),
+            hooks: vec![],
             deps: vec![],
             preset_name: preset.name().to_string(),
             preset: preset.clone(),
@@ -46,19 +94,36 @@ impl Spec {
         spec
     }
 
-    pub fn add_dependency(&mut self, mut dep: Dependency) {
+    /// Adds the dependency, or updates the existing one with the same name.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the name is already used by a
+    /// dependency with a different URL.
+    pub fn add_dependency(&mut self, mut dep: Dependency) -> Result<()> {
         dep.apply_preset(&self.preset);
-        if let Some(existing) = self.get_mut_dependency(&dep) {
-            existing.update_from(&dep);
-        } else {
-            self.deps.push(dep);
+        match self.get_mut_dependency(&dep) {
+            Some(existing) if !urls::same(&existing.url, &dep.url) => Err(format_err!(
+                "name '{}' is already used by {}, set a different name",
+                dep.name(),
+                existing.url
+            )),
+            Some(existing) => {
+                existing.update_from(&dep);
+                Ok(())
+            }
+            None => {
+                self.deps.push(dep);
+                Ok(())
+            }
         }
     }
 
     pub fn load_from(preset: &Preset) -> Result<Self> {
-        let mut spec: Self = yaml::load(preset.spec())?;
+        let mut spec: Self = yaml::load_migrated(preset.spec(), &schema::SPEC)?;
         spec.preset = preset.clone();
         spec.apply_preset();
+        spec.dedup_dependencies()?;
         Ok(spec)
     }
 
@@ -67,17 +132,134 @@ impl Spec {
         yaml::save(self, self.preset.spec())
     }
 
+    /// Returns the dependency identified by `handle`, which is either its
+    /// name or its URL, as long as no other dependency uses that URL.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if there is no such dependency,
+    /// or when the URL is ambiguous.
+    pub fn find_dependency(&self, handle: &str) -> Result<&Dependency> {
+        if let Some(dep) = self
+            .deps
+            .iter()
+            .find(|d| d.name().eq_ignore_ascii_case(handle))
+        {
+            return Ok(dep);
+        }
+
+        let found: Vec<_> = self
+            .deps
+            .iter()
+            .filter(|d| urls::same(&d.url, handle))
+            .collect();
+        match found[..] {
+            [dep] => Ok(dep),
+            [] => Err(format_err!("dependency {handle} not found in the spec")),
+            _ => Err(format_err!(
+                "several dependencies use {handle}, refer to them by name"
+            )),
+        }
+    }
+
+    /// Returns the problems found in the spec, see [`lint::check`].
+    pub fn problems(&self) -> Vec<lint::Problem> {
+        lint::check(self)
+    }
+
+    /// Fixes the mechanical problems of the spec, and returns them.
+    pub fn fix_problems(&mut self) -> Vec<lint::Problem> {
+        lint::fix(self)
+    }
+
+    /// Returns the policies the dependencies must follow: the one of the
+    /// preset and the one of the spec.
+    pub fn policies(&self) -> [&Policy; 2] {
+        [self.preset.policy(), &self.policy]
+    }
+
+    /// Returns the violations of the policies that can be found before the
+    /// dependency is fetched, see [`Policy::check_dependency`].
+    pub fn policy_violations(&self, dep: &Dependency) -> Vec<Violation> {
+        let filters = self.effective_filters(dep);
+        self.policies()
+            .iter()
+            .flat_map(|policy| policy.check_dependency(dep, &filters))
+            .collect()
+    }
+
+    /// Returns the filters a dependency is vendored with, the global ones
+    /// merged with the ones of the dependency.
+    pub fn effective_filters(&self, dep: &Dependency) -> Filters {
+        let mut filters = self.filters.clone();
+        filters.merge(&dep.filters);
+        filters
+    }
+
+    /// Returns the normalization of the dependency, its own or else the one
+    /// of the spec.
+    pub fn effective_normalize(&self, dep: &Dependency) -> Normalize {
+        dep.normalize.or(self.normalize).unwrap_or_default()
+    }
+
+    /// Returns a fingerprint of the settings that decide which files of the
+    /// dependency are vendored, where and how: the effective filters, the
+    /// preset, the vendor folder and the normalization, when there is one.
+    /// It is recorded in the lock to detect drift.
+    pub fn fingerprint(&self, dep: &Dependency) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(&self.preset_name);
+        hasher.update(b"\0");
+        hasher.update(&self.vendor);
+        hasher.update(b"\0");
+        hasher.update(serde_yaml::to_string(&self.effective_filters(dep)).unwrap_or_default());
+        let normalize = self.effective_normalize(dep);
+        if normalize != Normalize::default() {
+            hasher.update(b"\0");
+            hasher.update(serde_yaml::to_string(&normalize).unwrap_or_default());
+        }
+        format!("{:x}", hasher.finalize())
+    }
+
     fn get_mut_dependency(&mut self, dep: &Dependency) -> Option<&mut Dependency> {
+        let name = dep.name();
         self.deps
             .iter_mut()
-            .find(|d| d.url.eq_ignore_ascii_case(&dep.url))
+            .find(|d| d.name().eq_ignore_ascii_case(&name))
+    }
+
+    /// Removes the dependencies that repeat the name and the repository of
+    /// a previous one, eg. `https://github.com/a/b` and `git@github.com:a/b`.
+    fn dedup_dependencies(&mut self) -> Result<()> {
+        let mut unique: Vec<Dependency> = Vec::with_capacity(self.deps.len());
+        for dep in self.deps.drain(..) {
+            let name = dep.name();
+            match unique
+                .iter()
+                .find(|other| other.name().eq_ignore_ascii_case(&name))
+            {
+                Some(other) if urls::same(&other.url, &dep.url) => {
+                    log::warn!("{} is a duplicate of {}, ignoring it", dep.url, other.url);
+                }
+                Some(other) => {
+                    return Err(format_err!(
+                        "{} and {} are both named '{name}', set a unique name",
+                        other.url,
+                        dep.url
+                    ));
+                }
+                None => unique.push(dep),
+            }
+        }
+        self.deps = unique;
+        Ok(())
     }
 
     fn apply_preset(&mut self) {
-        let crate_version = VERSION.to_string();
-        if self.version < crate_version {
-            self.version = crate_version;
+        if versions::is_older(&self.version, VERSION) {
+            self.version = VERSION.to_string();
         }
+        self.schema_version = schema::SPEC.version();
         self.vendor = self.preset.vendor().to_string();
         if self.preset.force_filters() {
             self.filters.clear();
@@ -90,9 +272,11 @@ impl Spec {
     }
 
     fn lint(&mut self) {
-        self.deps.sort_by(|a, b| a.url.cmp(&b.url));
         self.deps
-            .dedup_by(|a, b| a.url.eq_ignore_ascii_case(&b.url));
+            .sort_by(|a, b| a.url.cmp(&b.url).then_with(|| a.name().cmp(&b.name())));
+        self.deps.dedup_by(|a, b| {
+            a.name().eq_ignore_ascii_case(&b.name()) && urls::same(&a.url, &b.url)
+        });
     }
 
     #[cfg(test)]
@@ -104,9 +288,14 @@ impl Spec {
 #[cfg(test)]
 mod tests {
 
+    use std::fs;
+
     use super::*;
+    use crate::filters::FilterKind;
+    use crate::svec;
     use crate::test_utils::build_preset;
     use crate::test_utils::TestContext;
+    use crate::transforms::Eol;
 
     #[test]
     fn test_spec_new() {
@@ -121,11 +310,11 @@ mod tests {
     }
 
     #[test]
-    fn test_spec_with_preset() {
+    fn test_spec_with_preset() -> Result<()> {
         let preset = build_preset();
         let dep = Dependency::new("some-url", "some-refname");
         let mut sut = Spec::with_preset(&preset);
-        sut.add_dependency(dep.clone());
+        sut.add_dependency(dep.clone())?;
 
         assert_eq!(VERSION, sut.version, "should have the crate version");
         assert_eq!(
@@ -145,19 +334,160 @@ mod tests {
         );
         assert_eq!("test-preset", sut.preset_name);
         assert_eq!(preset, sut.preset, "should use the provided preset");
+        Ok(())
     }
 
     #[test]
-    fn test_spec_add_dependency() {
+    fn test_spec_add_dependency() -> Result<()> {
         let preset = build_preset();
         let mut sut = Spec::with_preset(&preset);
         let mut dep = Dependency::new("some url", "some ref");
 
-        sut.add_dependency(dep.clone());
+        sut.add_dependency(dep.clone())?;
 
         assert_eq!(1, sut.deps.len());
         assert_ne!(&dep, &sut.deps[0]);
         assert_eq!(dep.apply_preset(&preset), &sut.deps[0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_spec_add_dependency_same_url_different_names() -> Result<()> {
+        let mut sut = Spec::new();
+        let mut v1 = Dependency::new("https://github.com/a/ledger", "v1");
+        v1.name = Some("ledger-v1".into());
+        let mut v2 = Dependency::new("https://github.com/a/ledger", "v2");
+        v2.name = Some("ledger-v2".into());
+
+        sut.add_dependency(v1)?;
+        sut.add_dependency(v2)?;
+        sut.add_dependency(Dependency::new("https://github.com/a/ledger", "v3"))?;
+        sut.lint();
+
+        assert_eq!(3, sut.deps.len());
+        assert_eq!("v1", sut.find_dependency("ledger-v1")?.refname);
+        assert_eq!("v3", sut.find_dependency("ledger")?.refname);
+        assert!(sut.find_dependency("https://github.com/a/ledger").is_err());
+        assert!(sut.find_dependency("missing").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_spec_add_dependency_name_conflict() -> Result<()> {
+        let mut sut = Spec::new();
+        sut.add_dependency(Dependency::new("https://github.com/a/ledger", "master"))?;
+
+        let result = sut.add_dependency(Dependency::new("https://github.com/b/ledger", "master"));
+
+        assert!(result.is_err(), "name is already used");
+        assert_eq!("https://github.com/a/ledger", sut.deps[0].url);
+        Ok(())
+    }
+
+    #[test]
+    fn test_spec_load_rejects_duplicated_names() -> Result<()> {
+        let ctx = TestContext::new();
+        fs::write(
+            ctx.preset.spec(),
+            "version: 0.1.0\nvendor: vendor\ndeps:\n- url: a/ledger\n  refname: master\n- url: \
+             b/ledger\n  refname: master\n",
+        )?;
+
+        let err = Spec::load_from(&ctx.preset).unwrap_err();
+
+        assert!(err.to_string().contains("named 'ledger'"), "{err}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_spec_load_dedups_equivalent_urls() -> Result<()> {
+        let ctx = TestContext::new();
+        fs::write(
+            ctx.preset.spec(),
+            "version: 0.1.0\nvendor: vendor\ndeps:\n- url: https://github.com/a/ledger\n  \
+             refname: master\n- url: git@github.com:a/ledger.git\n  refname: master\n",
+        )?;
+
+        let actual = Spec::load_from(&ctx.preset)?;
+
+        assert_eq!(1, actual.deps.len());
+        assert_eq!("https://github.com/a/ledger", actual.deps[0].url);
+        Ok(())
+    }
+
+    #[test]
+    fn test_spec_add_dependency_equivalent_url() -> Result<()> {
+        let mut sut = Spec::new();
+        sut.add_dependency(Dependency::new("https://github.com/a/ledger", "master"))?;
+
+        sut.add_dependency(Dependency::new("git@github.com:a/ledger.git", "v2"))?;
+
+        assert_eq!(1, sut.deps.len());
+        assert_eq!("v2", sut.deps[0].refname);
+        assert_eq!(
+            "https://github.com/a/ledger", sut.deps[0].url,
+            "keeps spelling"
+        );
+        assert_eq!(
+            "v2",
+            sut.find_dependency("ssh://git@github.com/a/ledger")?
+                .refname
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_spec_fingerprint() -> Result<()> {
+        let mut sut = Spec::new();
+        sut.add_dependency(Dependency::new("some-url", "some-refname"))?;
+        let dep = sut.deps[0].clone();
+        let original = sut.fingerprint(&dep);
+
+        sut.deps[0].refname = "other-refname".into();
+        assert_eq!(
+            original,
+            sut.fingerprint(&sut.deps[0]),
+            "refname is not part of it"
+        );
+
+        sut.deps[0]
+            .filters
+            .add(FilterKind::Target(svec!["some-target"]));
+        assert_ne!(
+            original,
+            sut.fingerprint(&sut.deps[0]),
+            "dependency filters"
+        );
+
+        sut.filters.add(FilterKind::Ignore(svec!["some-ignore"]));
+        assert_ne!(original, sut.fingerprint(&dep), "global filters");
+        Ok(())
+    }
+
+    #[test]
+    fn test_spec_fingerprint_normalize() -> Result<()> {
+        let mut sut = Spec::new();
+        sut.add_dependency(Dependency::new("some-url", "some-refname"))?;
+        let original = sut.fingerprint(&sut.deps[0]);
+
+        sut.normalize = Some(Normalize::default());
+        assert_eq!(original, sut.fingerprint(&sut.deps[0]), "no-op normalize");
+
+        let lf = Normalize {
+            eol: Eol::Lf,
+            strip_bom: false,
+        };
+        sut.normalize = Some(lf);
+        let global = sut.fingerprint(&sut.deps[0]);
+        assert_ne!(original, global, "global normalize");
+
+        sut.deps[0].normalize = Some(Normalize::default());
+        assert_eq!(Normalize::default(), sut.effective_normalize(&sut.deps[0]));
+        assert_eq!(original, sut.fingerprint(&sut.deps[0]), "overridden");
+
+        sut.deps[0].normalize = Some(lf);
+        assert_eq!(global, sut.fingerprint(&sut.deps[0]), "same normalize");
+        Ok(())
     }
 
     #[test]
@@ -178,7 +508,7 @@ mod tests {
         let ctx = TestContext::new();
         let dep = Dependency::new("some url", "some ref");
         let mut expected = Spec::with_preset(&ctx.preset);
-        expected.add_dependency(dep);
+        expected.add_dependency(dep)?;
 
         expected.save()?;
 
@@ -187,6 +517,36 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_spec_load_migrates_unversioned_spec() -> Result<()> {
+        let ctx = TestContext::new();
+        fs::write(
+            ctx.preset.spec(),
+            "version: 0.0.1\nvendor: vendor\ndeps:\n- url: some-url\n  refname: master\n",
+        )?;
+
+        let actual = Spec::load_from(&ctx.preset)?;
+
+        assert_eq!(schema::SPEC.version(), actual.schema_version);
+        assert_eq!(VERSION, actual.version);
+        assert_eq!(1, actual.deps.len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_spec_load_rejects_newer_schema() -> Result<()> {
+        let ctx = TestContext::new();
+        fs::write(
+            ctx.preset.spec(),
+            "version: 99.0.0\nschema_version: 999\nvendor: vendor\ndeps: []\n",
+        )?;
+
+        let err = Spec::load_from(&ctx.preset).unwrap_err();
+
+        assert!(err.to_string().contains("upgrade vendify"), "{err}");
+        Ok(())
+    }
+
     #[test]
     fn test_spec_cannot_load_from_non_existent_file() {
         let ctx = TestContext::new();
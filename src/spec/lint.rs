@@ -0,0 +1,325 @@
+use std::fmt;
+use std::path::Component;
+use std::path::Path;
+
+use super::Spec;
+use crate::filters::Filters;
+use crate::transforms::Transform;
+use crate::transforms::Transformer;
+
+/// A problem found in the spec, with the location of the offending value.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Problem {
+    pub location: String,
+    pub message: String,
+
+    /// Whether `lint --fix` can solve the problem mechanically.
+    pub fixable: bool,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)?;
+        if self.fixable {
+            write!(f, " (fixable with --fix)")?;
+        }
+        Ok(())
+    }
+}
+
+/// How a fixable value is fixed.
+enum Fix {
+    Replace(String),
+    Remove,
+}
+
+struct Finding {
+    message: String,
+    fix: Option<Fix>,
+}
+
+impl Finding {
+    fn new(message: impl Into<String>, fix: Option<Fix>) -> Self {
+        Self {
+            message: message.into(),
+            fix,
+        }
+    }
+}
+
+/// Returns all the problems of the spec.
+pub fn check(spec: &Spec) -> Vec<Problem> {
+    let mut problems = vec![];
+    check_filters("", &spec.filters, &mut problems);
+    check_vendor("", spec, &spec.filters, &mut problems);
+    check_transforms("", &spec.transforms, &mut problems);
+    for (i, provenance) in spec.provenance.iter().enumerate() {
+        if let Err(err) = provenance.check() {
+            problems.push(problem(
+                "",
+                &format!("provenance[{i}]"),
+                &err.to_string(),
+                false,
+            ));
+        }
+    }
+
+    for dep in &spec.deps {
+        let at = format!("deps[{}].", dep.name());
+        if dep.url.trim().is_empty() {
+            problems.push(problem(&at, "url", "is empty", false));
+        }
+        if dep.refname.trim().is_empty() {
+            problems.push(problem(&at, "refname", "is empty", false));
+        }
+        if dep
+            .name
+            .as_deref()
+            .is_some_and(|name| name.trim().is_empty())
+        {
+            problems.push(problem(&at, "name", "is empty", false));
+        }
+        check_filters(&at, &dep.filters, &mut problems);
+        check_vendor(&at, spec, &dep.filters, &mut problems);
+        check_transforms(&at, &dep.transforms, &mut problems);
+        if dep
+            .verify_signatures
+            .as_ref()
+            .is_some_and(|policy| policy.gpg_keys.is_none() && policy.ssh_allowed_signers.is_none())
+        {
+            problems.push(problem(
+                &at,
+                "verify_signatures",
+                "no gpg_keys nor ssh_allowed_signers, no signature would be valid",
+                false,
+            ));
+        }
+    }
+    problems
+}
+
+/// Fixes the mechanical problems of the spec, returns the problems that
+/// have been fixed.
+pub fn fix(spec: &mut Spec) -> Vec<Problem> {
+    let mut fixed = vec![];
+    fix_filters("", &mut spec.filters, &mut fixed);
+    for dep in &mut spec.deps {
+        let at = format!("deps[{}].", dep.name());
+        fix_filters(&at, &mut dep.filters, &mut fixed);
+    }
+    fixed
+}
+
+fn check_filters(at: &str, filters: &Filters, problems: &mut Vec<Problem>) {
+    for (field, values, check) in fields(filters) {
+        for (i, value) in values.iter().enumerate() {
+            if let Some(finding) = check(value) {
+                problems.push(problem(
+                    at,
+                    &format!("{field}[{i}]"),
+                    &format!("'{value}' {}", finding.message),
+                    finding.fix.is_some(),
+                ));
+            }
+        }
+    }
+}
+
+fn fix_filters(at: &str, filters: &mut Filters, fixed: &mut Vec<Problem>) {
+    let mut result = Filters::new();
+    for (field, values, check) in fields(filters) {
+        let mut kept = vec![];
+        for (i, value) in values.iter().enumerate() {
+            let finding = check(value);
+            match finding.as_ref().and_then(|finding| finding.fix.as_ref()) {
+                Some(Fix::Replace(replacement)) => kept.push(replacement.clone()),
+                Some(Fix::Remove) => {}
+                None => kept.push(value.clone()),
+            }
+            if let Some(finding) = finding.filter(|finding| finding.fix.is_some()) {
+                fixed.push(problem(
+                    at,
+                    &format!("{field}[{i}]"),
+                    &format!("'{value}' {}", finding.message),
+                    true,
+                ));
+            }
+        }
+        match field {
+            "targets" => result.targets = kept,
+            "ignores" => result.ignores = kept,
+            _ => result.extensions = kept,
+        }
+    }
+    // Re-adding sorts and dedups the values, as fixes may yield duplicates.
+    *filters = Filters::new();
+    filters.merge(&result);
+}
+
+type Check = fn(&str) -> Option<Finding>;
+
+fn fields(filters: &Filters) -> [(&'static str, &Vec<String>, Check); 3] {
+    [
+        ("targets", &filters.targets, check_path),
+        ("ignores", &filters.ignores, check_path),
+        ("extensions", &filters.extensions, check_extension),
+    ]
+}
+
+/// The vendor folder cannot be inside a target, or vendoring would copy
+/// the vendored files into themselves.
+fn check_vendor(at: &str, spec: &Spec, filters: &Filters, problems: &mut Vec<Problem>) {
+    for (i, target) in filters.targets.iter().enumerate() {
+        if !target.trim().is_empty() && Path::new(&spec.vendor).starts_with(target) {
+            problems.push(problem(
+                at,
+                &format!("targets[{i}]"),
+                &format!("vendor folder {} is inside '{target}'", spec.vendor),
+                false,
+            ));
+        }
+    }
+}
+
+fn check_transforms(at: &str, transforms: &[Transform], problems: &mut Vec<Problem>) {
+    if let Err(err) = Transformer::new(transforms) {
+        problems.push(problem(at, "transforms", &err.to_string(), false));
+    }
+}
+
+/// Checks a target or ignore path, which is relative to the root of the
+/// dependency repository.
+fn check_path(path: &str) -> Option<Finding> {
+    if path.trim().is_empty() {
+        return Some(Finding::new("is empty", Some(Fix::Remove)));
+    }
+    if Path::new(path)
+        .components()
+        .any(|component| component == Component::ParentDir)
+    {
+        return Some(Finding::new(
+            "contains '..', paths cannot escape the repository",
+            None,
+        ));
+    }
+    if path.starts_with('/') {
+        return Some(Finding::new(
+            "is absolute, paths are relative to the repository root",
+            Some(Fix::Replace(path.trim_start_matches('/').to_string())),
+        ));
+    }
+    None
+}
+
+fn check_extension(extension: &str) -> Option<Finding> {
+    if extension.trim().is_empty() {
+        return Some(Finding::new("is empty", Some(Fix::Remove)));
+    }
+    if extension.contains('/') {
+        return Some(Finding::new("is a path, not an extension", None));
+    }
+    if extension.starts_with('.') {
+        return Some(Finding::new(
+            "starts with a dot, it never matches",
+            Some(Fix::Replace(extension.trim_start_matches('.').to_string())),
+        ));
+    }
+    None
+}
+
+fn problem(at: &str, field: &str, message: &str, fixable: bool) -> Problem {
+    Problem {
+        location: format!("{at}{field}"),
+        message: message.to_string(),
+        fixable,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::deps::Dependency;
+    use crate::filters::FilterKind;
+    use crate::provenance::Provenance;
+    use crate::svec;
+
+    fn build_spec() -> Spec {
+        let mut spec = Spec::new();
+        spec.filters = Filters::new();
+        spec.filters
+            .add(FilterKind::Target(svec!["/proto", "vendor"]));
+        let mut dep = Dependency::new("https://github.com/a/ledger", "master");
+        dep.filters
+            .add(FilterKind::Target(svec!["../escape"]))
+            .add(FilterKind::Ignore(svec![""]))
+            .add(FilterKind::Extension(svec![".proto", "proto", "a/b"]));
+        spec.deps.push(dep);
+        spec.deps.push(Dependency::new("", "master"));
+        spec
+    }
+
+    fn locations(problems: &[Problem]) -> Vec<&str> {
+        problems.iter().map(|p| p.location.as_str()).collect()
+    }
+
+    #[test]
+    fn test_check() {
+        let spec = build_spec();
+
+        let problems = check(&spec);
+
+        assert_eq!(
+            vec![
+                "targets[0]",
+                "targets[1]",
+                "deps[ledger].targets[0]",
+                "deps[ledger].ignores[0]",
+                "deps[ledger].extensions[0]",
+                "deps[ledger].extensions[1]",
+                "deps[].url",
+            ],
+            locations(&problems)
+        );
+        assert!(problems[0].fixable);
+        assert!(!problems[2].fixable);
+        assert_eq!(
+            "deps[ledger].extensions[0]: '.proto' starts with a dot, it never matches (fixable \
+             with --fix)",
+            problems[4].to_string()
+        );
+    }
+
+    #[test]
+    fn test_check_provenance() {
+        let mut spec = Spec::new();
+        spec.provenance.push(Provenance {
+            template: "// {url}@{sha}".into(),
+            extensions: svec!["proto"],
+        });
+
+        let problems = check(&spec);
+
+        assert_eq!(vec!["provenance[0]"], locations(&problems));
+    }
+
+    #[test]
+    fn test_fix() {
+        let mut spec = build_spec();
+
+        let fixed = fix(&mut spec);
+
+        assert_eq!(
+            vec![
+                "targets[0]",
+                "deps[ledger].ignores[0]",
+                "deps[ledger].extensions[0]",
+            ],
+            locations(&fixed)
+        );
+        assert_eq!(svec!["proto", "vendor"], spec.filters.targets);
+        assert!(spec.deps[0].filters.ignores.is_empty());
+        assert_eq!(svec!["a/b", "proto"], spec.deps[0].filters.extensions);
+        assert!(check(&spec).iter().all(|problem| !problem.fixable));
+    }
+}
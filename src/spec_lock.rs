@@ -1,17 +1,26 @@
+use anyhow::format_err;
 use anyhow::Result;
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::deps::Dependency;
 use crate::deps::LockedDependency;
 use crate::preset::Preset;
+use crate::schema;
+use crate::urls;
+use crate::versions;
 use crate::yaml;
 use crate::VERSION;
 
-#[derive(Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Clone)]
 pub struct SpecLock {
     /// Version that was used to generate the config
     pub version: String,
 
+    /// Version of the schema of the lock, see [`schema::LOCK`].
+    #[serde(default)]
+    pub schema_version: u32,
+
     /// List of locked dependencies
     pub deps: Vec<LockedDependency>,
 
@@ -23,6 +32,7 @@ impl SpecLock {
     pub fn with_preset(preset: &Preset) -> Self {
         let mut lock = Self {
             version: VERSION.to_owned(),
+            schema_version: schema::LOCK.version(),
             deps: Vec::new(),
             preset: preset.clone(),
         };
@@ -31,27 +41,28 @@ impl SpecLock {
     }
 
     pub fn load_from(preset: &Preset) -> Result<Self> {
-        let mut lock: Self = yaml::load(preset.spec_lock())?;
+        let mut lock: Self = yaml::load_migrated(preset.spec_lock(), &schema::LOCK)?;
         lock.apply_preset(preset);
         Ok(lock)
     }
 
     pub fn save(&mut self) -> Result<()> {
-        self.lint();
+        self.lint()?;
         yaml::save(self, self.preset.spec_lock())
     }
 
     pub fn apply_preset(&mut self, preset: &Preset) {
-        if self.version < VERSION.into() {
+        if versions::is_older(&self.version, VERSION) {
             self.version = VERSION.into();
         }
+        self.schema_version = schema::LOCK.version();
         self.preset = preset.clone();
     }
 
     pub fn add_locked_dependency(&mut self, dep: LockedDependency) {
-        match self.get_mut_locked_dependency(&dep.url) {
+        match self.get_mut_locked_dependency(&dep.name) {
             Some(found) => {
-                found.refname = dep.refname;
+                *found = dep;
             }
             None => {
                 self.deps.push(dep);
@@ -59,20 +70,64 @@ impl SpecLock {
         }
     }
 
-    pub fn get_locked_dependency(&self, url: &str) -> Option<&LockedDependency> {
-        self.deps.iter().find(|l| l.url.eq_ignore_ascii_case(url))
+    /// Gives the names of `deps` to the entries locked under another name.
+    /// Entries migrated from locks without names are named after their URL,
+    /// see [`schema::LOCK`], which may differ from the name in the spec. A
+    /// dependency without entry of its own takes over the only entry with the
+    /// same URL, unless another dependency already owns it.
+    pub fn adopt_names(&mut self, deps: &[Dependency]) {
+        let owned = |lock: &LockedDependency| {
+            deps.iter().any(|dep| {
+                dep.name().eq_ignore_ascii_case(&lock.name) && urls::same(&dep.url, &lock.url)
+            })
+        };
+        for dep in deps {
+            let name = dep.name();
+            if self.get_locked_dependency(&name).is_some() {
+                continue;
+            }
+            let mut candidates = self
+                .deps
+                .iter_mut()
+                .filter(|lock| urls::same(&lock.url, &dep.url) && !owned(lock));
+            if let (Some(lock), None) = (candidates.next(), candidates.next()) {
+                log::debug!("{}: locked as {}", name, lock.name);
+                lock.name = name;
+            }
+        }
+    }
+
+    /// Returns the locked dependency named `name`.
+    pub fn get_locked_dependency(&self, name: &str) -> Option<&LockedDependency> {
+        self.deps.iter().find(|l| l.name.eq_ignore_ascii_case(name))
     }
 
-    fn get_mut_locked_dependency(&mut self, url: &str) -> Option<&mut LockedDependency> {
+    fn get_mut_locked_dependency(&mut self, name: &str) -> Option<&mut LockedDependency> {
         self.deps
             .iter_mut()
-            .find(|l| l.url.eq_ignore_ascii_case(url))
+            .find(|l| l.name.eq_ignore_ascii_case(name))
     }
 
-    fn lint(&mut self) {
-        self.deps.sort_by(|a, b| a.url.cmp(&b.url));
-        self.deps
-            .dedup_by(|a, b| a.url.eq_ignore_ascii_case(&b.url));
+    fn lint(&mut self) -> Result<()> {
+        self.deps.sort_by(|a, b| {
+            a.name
+                .to_lowercase()
+                .cmp(&b.name.to_lowercase())
+                .then_with(|| a.url.cmp(&b.url))
+        });
+        match self
+            .deps
+            .windows(2)
+            .find(|pair| pair[0].name.eq_ignore_ascii_case(&pair[1].name))
+        {
+            Some(pair) => Err(format_err!(
+                "{} is locked twice, with {} and {}, rename one of them in the spec",
+                pair[0].name,
+                pair[0].url,
+                pair[1].url
+            )),
+            None => Ok(()),
+        }
     }
 
     #[cfg(test)]
@@ -118,6 +173,57 @@ mod tests {
         assert_eq!(dep, sut.deps[0]);
     }
 
+    #[test]
+    fn test_spec_lock_adopt_names_of_migrated_entries() {
+        let mut sut = SpecLock::new();
+        sut.add_locked_dependency(LockedDependency::new("https://github.com/a/lib", "a"));
+        sut.deps
+            .push(LockedDependency::new("https://gitlab.com/b/lib", "b"));
+        let mut gitlab = Dependency::new("https://gitlab.com/b/lib.git", "main");
+        gitlab.name = Some("lib-b".into());
+        let deps = [Dependency::new("https://github.com/a/lib", "main"), gitlab];
+
+        sut.adopt_names(&deps);
+
+        assert_eq!("a", sut.get_locked_dependency("lib").unwrap().refname);
+        assert_eq!("b", sut.get_locked_dependency("lib-b").unwrap().refname);
+    }
+
+    #[test]
+    fn test_spec_lock_adopt_names_keeps_owned_entries() {
+        let mut sut = SpecLock::new();
+        sut.add_locked_dependency(LockedDependency::new("https://github.com/a/lib", "a"));
+        let mut docs = Dependency::new("https://github.com/a/lib", "main");
+        docs.name = Some("docs".into());
+        let deps = [Dependency::new("https://github.com/a/lib", "main"), docs];
+
+        sut.adopt_names(&deps);
+
+        assert!(sut.get_locked_dependency("lib").is_some());
+        assert!(sut.get_locked_dependency("docs").is_none());
+    }
+
+    #[test]
+    fn test_spec_lock_save_refuses_names_locked_twice() {
+        let ctx = TestContext::new();
+        let mut sut = SpecLock::with_preset(&ctx.preset);
+        sut.deps
+            .push(LockedDependency::new("https://github.com/a/lib", "a"));
+        sut.deps
+            .push(LockedDependency::new("https://example.com/lib", "x"));
+        sut.deps
+            .push(LockedDependency::new("https://gitlab.com/b/lib", "b"));
+
+        let err = sut.save().unwrap_err();
+
+        assert_eq!(
+            "lib is locked twice, with https://example.com/lib and https://github.com/a/lib, \
+             rename one of them in the spec",
+            err.to_string()
+        );
+        assert_eq!(3, sut.deps.len(), "no entry is dropped");
+    }
+
     #[test]
     fn test_spec_lock_apply_preset_updates_version() -> Result<()> {
         let ctx = TestContext::new();
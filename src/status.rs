@@ -0,0 +1,122 @@
+use anyhow::Result;
+
+use crate::cache::Cache;
+use crate::deps::Dependency;
+use crate::deps::LockedDependency;
+use crate::versions;
+
+/// Where a dependency stands compared to its upstream repository.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Status {
+    pub name: String,
+    pub refname: String,
+
+    /// Locked tag for semver ranges, or locked commit otherwise.
+    pub current: Option<String>,
+
+    /// What an update would lock: the newest tag within the semver range,
+    /// or the newest commit of the branch.
+    pub wanted: Option<String>,
+
+    /// Newest version of the repository, regardless of the refname.
+    pub latest: Option<String>,
+}
+
+impl Status {
+    /// Fetches the upstream repository of the dependency, and compares it
+    /// against the lock.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the repository cannot be
+    /// fetched.
+    pub fn check(cache: &Cache, dep: &Dependency, lock: Option<&LockedDependency>) -> Result<Self> {
+        let _repository_lock = cache.lock_repository(dep)?;
+        let repository = cache.get_repository(dep)?;
+        repository.fetch_tags()?;
+        let tags = repository.tags()?;
+
+        let (current, wanted) = match dep.version_req() {
+            Some(req) => (
+                lock.and_then(|lock| lock.version.clone()),
+                versions::highest(&tags, Some(&req), dep.prereleases).map(String::from),
+            ),
+            None => {
+                repository.fetch(&dep.refname)?;
+                (
+                    lock.map(|lock| lock.refname.clone()),
+                    Some(repository.get_remote_refname(&dep.refname)?),
+                )
+            }
+        };
+
+        Ok(Self {
+            name: dep.name(),
+            refname: dep.refname.clone(),
+            current,
+            wanted,
+            latest: versions::highest(&tags, None, dep.prereleases).map(String::from),
+        })
+    }
+
+    /// Whether an update would lock something different than the lock.
+    pub fn is_outdated(&self) -> bool {
+        self.wanted.is_some() && self.current != self.wanted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_utils::commit_file;
+    use crate::test_utils::init_upstream;
+    use crate::test_utils::tag_head;
+    use crate::test_utils::tempdir;
+    use crate::test_utils::TestContext;
+
+    #[test]
+    fn test_status_check_range() -> Result<()> {
+        let ctx = TestContext::new();
+        let root = tempdir();
+        let upstream = init_upstream(root.path());
+        commit_file(&upstream, "file.txt", "1.4");
+        tag_head(&upstream, "v1.4.0");
+        commit_file(&upstream, "file.txt", "1.5");
+        tag_head(&upstream, "v1.5.0");
+        commit_file(&upstream, "file.txt", "2.0");
+        tag_head(&upstream, "v2.0.0");
+        let cache = Cache::new(&ctx.preset);
+        cache.initialize()?;
+        let dep = Dependency::new(root.path().to_string_lossy(), "^1.4");
+        let mut lock = LockedDependency::new(&dep.url, "some-commit");
+        lock.version = Some("v1.4.0".into());
+
+        let sut = Status::check(&cache, &dep, Some(&lock))?;
+
+        assert_eq!(Some("v1.4.0".into()), sut.current);
+        assert_eq!(Some("v1.5.0".into()), sut.wanted);
+        assert_eq!(Some("v2.0.0".into()), sut.latest);
+        assert!(sut.is_outdated());
+        Ok(())
+    }
+
+    #[test]
+    fn test_status_check_branch() -> Result<()> {
+        let ctx = TestContext::new();
+        let root = tempdir();
+        let upstream = init_upstream(root.path());
+        let commit = commit_file(&upstream, "file.txt", "data");
+        let cache = Cache::new(&ctx.preset);
+        cache.initialize()?;
+        let dep = Dependency::new(root.path().to_string_lossy(), "master");
+        let lock = LockedDependency::new(&dep.url, commit.to_string());
+
+        let sut = Status::check(&cache, &dep, Some(&lock))?;
+
+        assert_eq!(Some(commit.to_string()), sut.wanted);
+        assert_eq!(None, sut.latest);
+        assert!(!sut.is_outdated());
+        Ok(())
+    }
+}
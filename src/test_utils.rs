@@ -1,6 +1,21 @@
 use std::fs;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::net::TcpListener;
 use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Stdio;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::thread;
 
+use git2::ObjectType;
+use git2::Oid;
+use git2::Repository;
+use git2::RepositoryInitOptions;
+use git2::Signature;
 use tempfile::TempDir;
 
 use crate::deps::Dependency;
@@ -37,6 +52,160 @@ pub fn read_to_string<P: AsRef<Path>>(src: &P) -> String {
         .unwrap_or_else(|_| panic!("cannot read path {}", src.as_ref().display()))
 }
 
+/// Initializes a git repository in `path`, with `master` as initial branch,
+/// meant to be used as upstream of a [`Dependency`].
+pub fn init_upstream<P: AsRef<Path>>(path: P) -> Repository {
+    let mut opts = RepositoryInitOptions::new();
+    opts.initial_head("master");
+    Repository::init_opts(path, &opts).expect("cannot init upstream repository")
+}
+
+/// Writes `data` into `name` and commits it on top of the current HEAD.
+pub fn commit_file(repo: &Repository, name: &str, data: &str) -> Oid {
+    let workdir = repo.workdir().expect("upstream has no workdir");
+    let path = workdir.join(name);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).expect("cannot create upstream dirs");
+    }
+    write_to(&path, data);
+    commit_path(repo, name)
+}
+
+/// Commits a symbolic link `name` pointing to `target`, which may not exist.
+pub fn commit_symlink(repo: &Repository, name: &str, target: &str) -> Oid {
+    let workdir = repo.workdir().expect("upstream has no workdir");
+    std::os::unix::fs::symlink(target, workdir.join(name)).expect("cannot create upstream link");
+    commit_path(repo, name)
+}
+
+fn commit_path(repo: &Repository, name: &str) -> Oid {
+    let mut index = repo.index().unwrap();
+    index.add_path(Path::new(name)).unwrap();
+    index.write().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let signature = Signature::now("test", "test@example.com").unwrap();
+    let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+    let parents: Vec<_> = parent.iter().collect();
+    repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        &format!("add {name}"),
+        &tree,
+        &parents,
+    )
+    .unwrap()
+}
+
+/// Creates a lightweight tag `name` pointing to the current HEAD.
+pub fn tag_head(repo: &Repository, name: &str) -> Oid {
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    repo.tag_lightweight(name, head.as_object(), false)
+        .expect("cannot create upstream tag")
+}
+
+/// Generates an SSH key for the principal in `dir`, returns the path of the
+/// private key and of an allowed signers file that trusts it.
+pub fn ssh_key(dir: &Path, principal: &str) -> (PathBuf, PathBuf) {
+    fs::create_dir_all(dir).expect("cannot create key dir");
+    let key = dir.join("key");
+    let status = Command::new("ssh-keygen")
+        .args(["-q", "-t", "ed25519", "-N", "", "-C", principal, "-f"])
+        .arg(&key)
+        .stdin(Stdio::null())
+        .status()
+        .expect("cannot run ssh-keygen");
+    assert!(status.success(), "cannot generate ssh key");
+
+    let public = read_to_string(&key.with_extension("pub"));
+    let allowed_signers = dir.join("allowed_signers");
+    write_to(&allowed_signers, &format!("{principal} {public}"));
+    (key, allowed_signers)
+}
+
+/// Signs the data with the SSH key, in the namespace git uses.
+pub fn ssh_sign(key: &Path, data: &[u8]) -> Vec<u8> {
+    let file = key.with_extension("data");
+    fs::write(&file, data).expect("cannot write data to sign");
+    let status = Command::new("ssh-keygen")
+        .args(["-q", "-Y", "sign", "-n", "git", "-f"])
+        .arg(key)
+        .arg(&file)
+        .stdin(Stdio::null())
+        .status()
+        .expect("cannot run ssh-keygen");
+    assert!(status.success(), "cannot sign data");
+    fs::read(file.with_extension("data.sig")).expect("cannot read signature")
+}
+
+/// Like [`commit_file`], but the commit is signed with the SSH key and
+/// HEAD is moved to it.
+pub fn commit_signed_file(repo: &Repository, name: &str, data: &str, key: &Path) -> Oid {
+    let unsigned = commit_file(repo, name, data);
+    let commit = repo.find_commit(unsigned).unwrap();
+    let parents: Vec<_> = commit.parents().collect();
+    let buffer = repo
+        .commit_create_buffer(
+            &commit.author(),
+            &commit.committer(),
+            commit.message().unwrap(),
+            &commit.tree().unwrap(),
+            &parents.iter().collect::<Vec<_>>(),
+        )
+        .unwrap();
+    let content = buffer.as_str().unwrap();
+    let signature = String::from_utf8(ssh_sign(key, content.as_bytes())).unwrap();
+    let signed = repo.commit_signed(content, &signature, None).unwrap();
+
+    let head = repo.head().unwrap();
+    repo.reference(head.name().unwrap(), signed, true, "sign")
+        .unwrap();
+    signed
+}
+
+/// Creates an annotated tag `name` pointing to the current HEAD, signed with
+/// the SSH key.
+pub fn tag_signed_head(repo: &Repository, name: &str, key: &Path) -> Oid {
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    let content = format!(
+        "object {}\ntype commit\ntag {name}\ntagger test <test@example.com> 0 +0000\n\n{name}\n",
+        head.id()
+    );
+    let mut buffer = content.into_bytes();
+    let signature = ssh_sign(key, &buffer);
+    buffer.extend(signature);
+    let oid = repo
+        .odb()
+        .unwrap()
+        .write(ObjectType::Tag, &buffer)
+        .expect("cannot write upstream tag");
+    repo.reference(&format!("refs/tags/{name}"), oid, false, "tag")
+        .expect("cannot create upstream tag");
+    oid
+}
+
+/// Serves `response` to every HTTP request made to the returned local
+/// port, sending the request lines to the receiver.
+pub fn http_stub(response: &'static str) -> (u16, Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("cannot bind http stub");
+    let port = listener.local_addr().expect("http stub address").port();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let mut request_line = String::new();
+            let mut reader = BufReader::new(&stream);
+            let _ = reader.read_line(&mut request_line);
+            let mut header = String::new();
+            while reader.read_line(&mut header).is_ok_and(|n| n > 2) {
+                header.clear();
+            }
+            let _ = sender.send(request_line.trim().to_string());
+            let _ = stream.write_all(response.as_bytes());
+        }
+    });
+    (port, receiver)
+}
+
 pub fn build_preset() -> Preset {
     preset_builder().build()
 }
@@ -0,0 +1,370 @@
+use std::path::Path;
+
+use anyhow::format_err;
+use anyhow::Result;
+use regex::bytes::Regex;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::deps::is_default;
+use crate::deps::is_false;
+use crate::provenance;
+use crate::provenance::Origin;
+use crate::provenance::Provenance;
+
+/// Built-in transformation applied to the vendored files while copying them.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Transform {
+    /// Prepends `text` to the file, followed by a new line.
+    Header {
+        text: String,
+
+        #[serde(default, skip_serializing_if = "<[_]>::is_empty")]
+        extensions: Vec<String>,
+    },
+
+    /// Replaces every match of the regular expression `pattern` with `with`,
+    /// which can refer to capture groups, eg. `$1`.
+    Replace {
+        pattern: String,
+        with: String,
+
+        #[serde(default, skip_serializing_if = "<[_]>::is_empty")]
+        extensions: Vec<String>,
+    },
+}
+
+/// Normalization of the line endings and byte order mark of the vendored
+/// text files, binary files are never normalized.
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
+pub struct Normalize {
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub eol: Eol,
+
+    /// Whether the UTF-8 byte order mark is removed.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub strip_bom: bool,
+}
+
+/// Line endings the vendored text files are converted to.
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Eol {
+    /// Line endings are left as they are.
+    #[default]
+    Keep,
+    Lf,
+    Crlf,
+}
+
+const BOM: &[u8] = b"\xef\xbb\xbf";
+
+/// Applies a list of [`Transform`] in order, it is built once per
+/// dependency so regular expressions are only compiled once.
+#[derive(Default)]
+pub struct Transformer {
+    steps: Vec<(Step, Vec<String>)>,
+    normalize: Normalize,
+    provenance: Vec<(Vec<u8>, Vec<String>)>,
+}
+
+enum Step {
+    Header(Vec<u8>),
+    Replace(Regex, Vec<u8>),
+}
+
+impl Transformer {
+    /// Builds a [`Transformer`] from the transforms.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a pattern is not a valid
+    /// regular expression.
+    pub fn new(transforms: &[Transform]) -> Result<Self> {
+        let steps = transforms
+            .iter()
+            .map(|transform| match transform {
+                Transform::Header { text, extensions } => {
+                    let mut header = text.clone().into_bytes();
+                    if !header.ends_with(b"\n") {
+                        header.push(b'\n');
+                    }
+                    Ok((Step::Header(header), extensions.clone()))
+                }
+                Transform::Replace {
+                    pattern,
+                    with,
+                    extensions,
+                } => {
+                    let regex = Regex::new(pattern)
+                        .map_err(|err| format_err!("invalid replace pattern '{pattern}': {err}"))?;
+                    Ok((
+                        Step::Replace(regex, with.clone().into_bytes()),
+                        extensions.clone(),
+                    ))
+                }
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            steps,
+            normalize: Normalize::default(),
+            provenance: vec![],
+        })
+    }
+
+    /// Sets the normalization applied to the text files.
+    #[must_use]
+    pub fn with_normalize(mut self, normalize: Normalize) -> Self {
+        self.normalize = normalize;
+        self
+    }
+
+    /// Sets the provenance headers, rendered for the origin of the files.
+    #[must_use]
+    pub fn with_provenance(mut self, provenance: &[Provenance], origin: &Origin) -> Self {
+        self.provenance = provenance
+            .iter()
+            .map(|it| {
+                let mut header = it.render(origin).into_bytes();
+                header.push(b'\n');
+                (header, it.extensions.clone())
+            })
+            .collect();
+        self
+    }
+
+    /// Applies the transforms that target the path extension, the
+    /// provenance header and the normalization. The byte order mark is
+    /// stripped before the transforms and the line endings converted after
+    /// the header, so headers follow suit. Binary contents, see
+    /// [`is_binary`], are left untouched.
+    pub fn apply(&self, path: &Path, mut contents: Vec<u8>) -> Vec<u8> {
+        if (self.steps.is_empty()
+            && self.provenance.is_empty()
+            && self.normalize == Normalize::default())
+            || is_binary(&contents)
+        {
+            return contents;
+        }
+
+        if self.normalize.strip_bom && contents.starts_with(BOM) {
+            contents.drain(..BOM.len());
+        }
+        let contents = self
+            .steps
+            .iter()
+            .filter(|(_, extensions)| matches_extension(path, extensions))
+            .fold(contents, |contents, (step, _)| match step {
+                Step::Header(header) => {
+                    let mut out = header.clone();
+                    out.extend(contents);
+                    out
+                }
+                Step::Replace(regex, with) => regex.replace_all(&contents, with.as_slice()).into(),
+            });
+        let contents = match self
+            .provenance
+            .iter()
+            .find(|(_, extensions)| matches_extension(path, extensions))
+        {
+            Some((header, _)) => provenance::insert(&contents, header),
+            None => contents,
+        };
+        convert_eol(contents, self.normalize.eol)
+    }
+}
+
+/// Whether the contents are binary: they contain a NUL byte, like images or
+/// descriptor sets do, or they are not valid UTF-8.
+pub fn is_binary(contents: &[u8]) -> bool {
+    contents.contains(&0) || std::str::from_utf8(contents).is_err()
+}
+
+fn convert_eol(contents: Vec<u8>, eol: Eol) -> Vec<u8> {
+    if eol == Eol::Keep {
+        return contents;
+    }
+
+    let mut out = Vec::with_capacity(contents.len());
+    let mut bytes = contents.iter().peekable();
+    while let Some(&byte) = bytes.next() {
+        if byte == b'\r' && bytes.peek() == Some(&&b'\n') {
+            continue;
+        }
+        if byte == b'\n' && eol == Eol::Crlf {
+            out.push(b'\r');
+        }
+        out.push(byte);
+    }
+    out
+}
+
+pub(crate) fn matches_extension(path: &Path, extensions: &[String]) -> bool {
+    extensions.is_empty()
+        || path.extension().is_some_and(|ext| {
+            extensions
+                .iter()
+                .any(|target| ext.eq_ignore_ascii_case(target))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::svec;
+
+    fn apply(transforms: &[Transform], path: &str, contents: &str) -> String {
+        let sut = Transformer::new(transforms).expect("valid transforms");
+        String::from_utf8(sut.apply(Path::new(path), contents.as_bytes().to_vec())).unwrap()
+    }
+
+    #[test]
+    fn test_transformer_header() {
+        let transforms = &[Transform::Header {
+            text: "// DO NOT EDIT".into(),
+            extensions: vec![],
+        }];
+
+        assert_eq!(
+            "// DO NOT EDIT\nsyntax = \"proto3\";\n",
+            apply(transforms, "a.proto", "syntax = \"proto3\";\n")
+        );
+    }
+
+    #[test]
+    fn test_transformer_replace() {
+        let transforms = &[Transform::Replace {
+            pattern: r#"go_package = "([^"]+)""#.into(),
+            with: r#"go_package = "example.com/$1""#.into(),
+            extensions: vec![],
+        }];
+
+        assert_eq!(
+            r#"option go_package = "example.com/ledger/pb";"#,
+            apply(transforms, "a.proto", r#"option go_package = "ledger/pb";"#)
+        );
+    }
+
+    #[test]
+    fn test_transformer_applies_in_order_and_respects_extensions() {
+        let transforms = &[
+            Transform::Replace {
+                pattern: "a".into(),
+                with: "b".into(),
+                extensions: svec!["proto"],
+            },
+            Transform::Header {
+                text: "# a".into(),
+                extensions: svec!["py"],
+            },
+        ];
+
+        assert_eq!("bbc", apply(transforms, "x.proto", "abc"));
+        assert_eq!("# a\nabc", apply(transforms, "x.py", "abc"));
+        assert_eq!("abc", apply(transforms, "x.txt", "abc"));
+    }
+
+    #[test]
+    fn test_transformer_ignores_binary_contents() {
+        let sut = Transformer::new(&[Transform::Header {
+            text: "header".into(),
+            extensions: vec![],
+        }])
+        .unwrap();
+        let binary = vec![0xff, 0xfe, 0x00];
+
+        assert_eq!(binary, sut.apply(Path::new("a.bin"), binary.clone()));
+    }
+
+    #[test]
+    fn test_transformer_normalize() {
+        let normalize =
+            |eol, strip_bom| Transformer::default().with_normalize(Normalize { eol, strip_bom });
+        let apply = |sut: Transformer, contents: &str| {
+            String::from_utf8(sut.apply(Path::new("a.txt"), contents.as_bytes().to_vec())).unwrap()
+        };
+
+        assert_eq!("a\nb\nc", apply(normalize(Eol::Lf, false), "a\r\nb\nc"));
+        assert_eq!("a\r\nb\r\n", apply(normalize(Eol::Crlf, false), "a\r\nb\n"));
+        assert_eq!("a\r\nb\n", apply(normalize(Eol::Keep, false), "a\r\nb\n"));
+        assert_eq!("a\n", apply(normalize(Eol::Keep, true), "\u{feff}a\n"));
+        assert_eq!(
+            "\u{feff}a\n",
+            apply(normalize(Eol::Keep, false), "\u{feff}a\n")
+        );
+    }
+
+    #[test]
+    fn test_transformer_normalize_header_and_binary() {
+        let sut = Transformer::new(&[Transform::Header {
+            text: "// header".into(),
+            extensions: vec![],
+        }])
+        .unwrap()
+        .with_normalize(Normalize {
+            eol: Eol::Crlf,
+            strip_bom: true,
+        });
+        let binary = b"\x89PNG\r\n\x1a\n\x00\x00".to_vec();
+
+        assert_eq!(
+            b"// header\r\na\r\n".to_vec(),
+            sut.apply(Path::new("a.txt"), b"\xef\xbb\xbfa\n".to_vec())
+        );
+        assert_eq!(binary, sut.apply(Path::new("a.png"), binary.clone()));
+    }
+
+    #[test]
+    fn test_transformer_provenance() {
+        let origin = Origin {
+            name: "ledger",
+            url: "some-url",
+            commit: "0123abcd",
+            version: None,
+        };
+        let sut = Transformer::default()
+            .with_provenance(
+                &[
+                    Provenance {
+                        template: "// {url}@{commit}".into(),
+                        extensions: svec!["proto"],
+                    },
+                    Provenance {
+                        template: "# {name}".into(),
+                        extensions: svec!["sh"],
+                    },
+                ],
+                &origin,
+            )
+            .with_normalize(Normalize {
+                eol: Eol::Crlf,
+                strip_bom: false,
+            });
+
+        assert_eq!(
+            b"// some-url@0123abcd\r\na\r\n".to_vec(),
+            sut.apply(Path::new("a.proto"), b"a\n".to_vec())
+        );
+        assert_eq!(
+            b"a\r\n".to_vec(),
+            sut.apply(Path::new("a.py"), b"a\n".to_vec())
+        );
+        assert_eq!(
+            b"#!/bin/sh\r\n# ledger\r\necho\r\n".to_vec(),
+            sut.apply(Path::new("a.sh"), b"#!/bin/sh\necho\n".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_transformer_invalid_pattern() {
+        let result = Transformer::new(&[Transform::Replace {
+            pattern: "(".into(),
+            with: String::new(),
+            extensions: vec![],
+        }]);
+
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,177 @@
+/// Default ports, dropped from the normalized URLs.
+const DEFAULT_PORTS: &[(&str, &str)] = &[
+    ("ssh", "22"),
+    ("git", "9418"),
+    ("http", "80"),
+    ("https", "443"),
+];
+
+/// Returns the canonical form of a git remote URL, so the different ways
+/// of spelling the same repository are equal: scp-style, ssh, http(s) and
+/// git remotes become `host/path`, file URLs become the path, and the
+/// trailing `.git` and slashes are removed. It is meant for identity, the
+/// original URL is still the one fetched.
+pub fn normalize(url: &str) -> String {
+    let (host, path) = split(url);
+    let path = path.trim_end_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    match host {
+        Some(host) => format!("{host}/{}", path.trim_start_matches('/')),
+        None => path.to_string(),
+    }
+}
+
+/// Returns the host of a remote URL, with its port unless it is the default
+/// one, eg. `github.com` for `git@github.com:a/b`. Local paths and file URLs
+/// have no host.
+pub fn host(url: &str) -> Option<String> {
+    split(url).0
+}
+
+/// Whether both URLs point to the same repository, see [`normalize`]. Hosts
+/// are compared regardless of case, paths are case sensitive.
+pub fn same(a: &str, b: &str) -> bool {
+    normalize(a) == normalize(b)
+}
+
+/// Resolves a submodule URL against the URL of its superproject, like git
+/// does: URLs starting with `./` or `../` are relative to it, each `../`
+/// dropping its last path segment. Other URLs are returned as they are.
+pub fn resolve(base: &str, url: &str) -> String {
+    if !url.starts_with("./") && !url.starts_with("../") {
+        return url.to_string();
+    }
+    let mut base = base.trim_end_matches('/').to_string();
+    let mut rest = url;
+    loop {
+        if let Some(it) = rest.strip_prefix("./") {
+            rest = it;
+        } else if let Some(it) = rest.strip_prefix("../") {
+            match base.rfind(['/', ':']) {
+                Some(i) if base[i..].starts_with(':') => base.truncate(i + 1),
+                Some(i) => base.truncate(i),
+                None => base.clear(),
+            }
+            rest = it;
+        } else {
+            break;
+        }
+    }
+    if base.ends_with(':') {
+        format!("{base}{rest}")
+    } else {
+        format!("{base}/{rest}")
+    }
+}
+
+/// Splits the URL into its host, when it is a remote, and its path.
+fn split(url: &str) -> (Option<String>, &str) {
+    let url = url.trim();
+    match url.split_once("://") {
+        Some((scheme, rest)) if scheme.eq_ignore_ascii_case("file") => (None, rest),
+        Some((scheme, rest)) => {
+            let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+            (Some(authority_host(authority, Some(scheme))), path)
+        }
+        None => match url.split_once(':') {
+            // scp-style, eg. `git@github.com:a/b`, drive letters are paths.
+            Some((authority, path)) if authority.len() > 1 && !authority.contains('/') => {
+                (Some(authority_host(authority, None)), path)
+            }
+            _ => (None, url),
+        },
+    }
+}
+
+/// Returns the host of the authority, without user info and default port.
+fn authority_host(authority: &str, scheme: Option<&str>) -> String {
+    let host = authority.rsplit('@').next().unwrap_or(authority);
+    let host = match (host.rsplit_once(':'), scheme) {
+        (Some((name, port)), Some(scheme))
+            if DEFAULT_PORTS
+                .iter()
+                .any(|(s, p)| scheme.eq_ignore_ascii_case(s) && port == *p) =>
+        {
+            name
+        }
+        _ => host,
+    };
+    host.to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_normalize_equivalent_remotes() {
+        for url in [
+            "https://github.com/a/b",
+            "https://github.com/a/b.git",
+            "https://github.com/a/b/",
+            "http://GitHub.com:80/a/b",
+            "git@github.com:a/b.git",
+            "ssh://git@github.com/a/b",
+            "ssh://git@github.com:22/a/b.git",
+            "git://github.com/a/b",
+        ] {
+            assert_eq!("github.com/a/b", normalize(url), "{url}");
+        }
+    }
+
+    #[test]
+    fn test_normalize_keeps_distinct_remotes() {
+        assert_eq!(
+            "github.com:8443/a/b",
+            normalize("https://github.com:8443/a/b")
+        );
+        assert_eq!("gitlab.com/a/b", normalize("git@gitlab.com:a/b"));
+    }
+
+    #[test]
+    fn test_normalize_local_paths() {
+        assert_eq!("/tmp/a/b", normalize("file:///tmp/a/b.git"));
+        assert_eq!("/tmp/a/b", normalize("/tmp/a/b/"));
+        assert_eq!("C:/a/b", normalize("C:/a/b"));
+        assert_eq!("some-url", normalize("some-url"));
+    }
+
+    #[test]
+    fn test_host() {
+        assert_eq!(Some("github.com".into()), host("git@github.com:a/b"));
+        assert_eq!(
+            Some("git.corp:8443".into()),
+            host("https://user@git.corp:8443/a/b")
+        );
+        assert_eq!(None, host("file:///tmp/a/b"));
+        assert_eq!(None, host("/tmp/a/b"));
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(
+            "https://github.com/a/c.git",
+            resolve("https://github.com/a/b.git", "../c.git")
+        );
+        assert_eq!(
+            "https://github.com/a/b/c",
+            resolve("https://github.com/a/b/", "./c")
+        );
+        assert_eq!("git@github.com:a/c", resolve("git@github.com:a/b", "../c"));
+        assert_eq!("git@github.com:c", resolve("git@github.com:b", "../c"));
+        assert_eq!("/tmp/c", resolve("/tmp/a/b", "../../c"));
+        assert_eq!(
+            "https://gitlab.com/c",
+            resolve("https://github.com/a/b", "https://gitlab.com/c")
+        );
+    }
+
+    #[test]
+    fn test_same() {
+        assert!(same("https://GitHub.com/a/b", "git@github.com:a/b.git"));
+        assert!(!same("https://github.com/a/b", "https://github.com/a/c"));
+        assert!(!same("https://github.com/A/b", "https://github.com/a/b"));
+        assert!(!same("/srv/Repo", "/srv/repo"));
+    }
+}
@@ -0,0 +1,154 @@
+use semver::BuildMetadata;
+use semver::Comparator;
+use semver::Op;
+use semver::Prerelease;
+use semver::Version;
+use semver::VersionReq;
+
+/// Characters a refname has to start with to be considered a semver range,
+/// so branch names like `1.x` keep working as before.
+const RANGE_OPERATORS: &[char] = &['^', '~', '=', '>', '<', '*'];
+
+/// Parses a refname as a semver range, eg. `^1.4` or `~2.0.3`. Returns
+/// `None` when the refname is a branch, tag or commit.
+pub fn parse_range(refname: &str) -> Option<VersionReq> {
+    if !refname.starts_with(RANGE_OPERATORS) {
+        return None;
+    }
+    VersionReq::parse(refname).ok()
+}
+
+/// Parses a tag as a version, tags can be prefixed with `v`.
+pub fn parse_tag(tag: &str) -> Option<Version> {
+    Version::parse(tag.strip_prefix('v').unwrap_or(tag)).ok()
+}
+
+/// Whether the semver `version` is older than `than`. Versions that cannot be
+/// parsed are considered older.
+pub fn is_older(version: &str, than: &str) -> bool {
+    match (Version::parse(version), Version::parse(than)) {
+        (Ok(version), Ok(than)) => version < than,
+        _ => true,
+    }
+}
+
+/// Returns the tag with the highest version that satisfies `req`, or the
+/// highest overall when there is no requirement. Pre-releases are ignored,
+/// unless `prereleases` is set or the requirement explicitly targets them.
+/// A pre-release is accepted when its release satisfies `req` and it is not
+/// below the lower bound of the range, so `1.4.0-rc.1` is not within `^1.4`.
+pub fn highest<'a>(
+    tags: &'a [String],
+    req: Option<&VersionReq>,
+    prereleases: bool,
+) -> Option<&'a str> {
+    tags.iter()
+        .filter_map(|tag| parse_tag(tag).map(|version| (tag, version)))
+        .filter(|(_, version)| match req {
+            Some(req) if prereleases && !version.pre.is_empty() => {
+                let release = Version {
+                    pre: Prerelease::EMPTY,
+                    ..version.clone()
+                };
+                req.matches(&release)
+                    && req
+                        .comparators
+                        .iter()
+                        .filter_map(lower_bound)
+                        .all(|bound| *version >= bound)
+            }
+            Some(req) => req.matches(version),
+            None => prereleases || version.pre.is_empty(),
+        })
+        .max_by(|(_, a), (_, b)| a.cmp(b))
+        .map(|(tag, _)| tag.as_str())
+}
+
+/// Returns the lowest version the comparator accepts, `None` when it has no
+/// lower bound, eg. `<2`.
+fn lower_bound(comparator: &Comparator) -> Option<Version> {
+    match comparator.op {
+        Op::Less | Op::LessEq => None,
+        _ => Some(Version {
+            major: comparator.major,
+            minor: comparator.minor.unwrap_or(0),
+            patch: comparator.patch.unwrap_or(0),
+            pre: comparator.pre.clone(),
+            build: BuildMetadata::EMPTY,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::svec;
+
+    fn tags() -> Vec<String> {
+        svec![
+            "v1.3.0",
+            "v1.4.0",
+            "1.5.2",
+            "v1.6.0-rc.1",
+            "v2.0.0",
+            "latest"
+        ]
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert!(parse_range("^1.4").is_some());
+        assert!(parse_range("~2.0.3").is_some());
+        assert!(parse_range(">=1, <2").is_some());
+        assert!(parse_range("master").is_none());
+        assert!(parse_range("1.4").is_none());
+        assert!(parse_range("v1.4.0").is_none());
+    }
+
+    #[test]
+    fn test_parse_tag() {
+        assert_eq!(Some(Version::new(1, 4, 0)), parse_tag("v1.4.0"));
+        assert_eq!(Some(Version::new(1, 4, 0)), parse_tag("1.4.0"));
+        assert_eq!(None, parse_tag("latest"));
+    }
+
+    #[test]
+    fn test_is_older() {
+        assert!(is_older("0.9.0", "0.10.0"));
+        assert!(!is_older("0.10.0", "0.9.0"));
+        assert!(!is_older("0.9.0", "0.9.0"));
+        assert!(is_older("invalid", "0.9.0"));
+    }
+
+    #[test]
+    fn test_highest() {
+        let tags = &tags();
+        let req = parse_range("^1.4").unwrap();
+
+        assert_eq!(Some("1.5.2"), highest(tags, Some(&req), false));
+        assert_eq!(Some("v1.6.0-rc.1"), highest(tags, Some(&req), true));
+        assert_eq!(Some("v2.0.0"), highest(tags, None, false));
+        assert_eq!(None, highest(tags, parse_range("^3").as_ref(), false));
+    }
+
+    #[test]
+    fn test_highest_prerelease_below_range() {
+        let tags = &svec!["v1.3.0", "v1.4.0-rc.1"];
+        let req = parse_range("^1.4").unwrap();
+
+        assert_eq!(None, highest(tags, Some(&req), true));
+        assert_eq!(
+            Some("v1.4.0-rc.1"),
+            highest(tags, parse_range(">=1.3, <2").as_ref(), true)
+        );
+    }
+
+    #[test]
+    fn test_highest_explicit_prerelease() {
+        let tags = &tags();
+        let req = parse_range("^1.6.0-rc.0").unwrap();
+
+        assert_eq!(Some("v1.6.0-rc.1"), highest(tags, Some(&req), false));
+    }
+}
@@ -6,6 +6,9 @@ use anyhow::Result;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 
+use crate::atomic;
+use crate::schema::Schema;
+
 pub fn load<T: Sized + DeserializeOwned, P: AsRef<Path>>(path: P) -> Result<T> {
     let do_load = || -> Result<T> {
         let f = fs::File::open(&path)?;
@@ -23,10 +26,26 @@ pub fn load<T: Sized + DeserializeOwned, P: AsRef<Path>>(path: P) -> Result<T> {
     }
 }
 
+/// Loads a document, upgrading it to the current version of the `schema`
+/// before deserializing it.
+pub fn load_migrated<T: Sized + DeserializeOwned, P: AsRef<Path>>(
+    path: P,
+    schema: &Schema,
+) -> Result<T> {
+    let do_load = || -> Result<T> {
+        let f = fs::File::open(&path)?;
+        let document = schema.migrate(serde_yaml::from_reader(&f)?)?;
+        Ok(serde_yaml::from_value(document)?)
+    };
+
+    do_load()
+        .map_err(|err| format_err!("cannot load {path}: {err}", path = path.as_ref().display()))
+}
+
 pub fn save<T: Sized + Serialize, P: AsRef<Path>>(input: &T, path: P) -> Result<()> {
     let do_save = || -> Result<()> {
         let contents = serde_yaml::to_string(input)?;
-        fs::write(&path, contents)?;
+        atomic::write(&path, contents)?;
         Ok(())
     };
 